@@ -0,0 +1,202 @@
+// --------------------------------------------------------------------------
+// When during the day / week time is spent. Sessions are cut at every local
+// hour boundary and each piece is added to the bucket it falls in, so a
+// session from 9:50 to 10:20 counts ten minutes toward 9:00 and twenty
+// toward 10:00. Weekdays follow the day start, like the calendar and the
+// reports do. Idle time is left out.
+// --------------------------------------------------------------------------
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, Datelike, Local, NaiveDate, TimeZone, Timelike};
+use rusqlite::{Connection, Result};
+use tauri::State;
+
+use crate::days::DayBoundary;
+use crate::db::Db;
+use crate::error::DeskflowError;
+use crate::reports::parse_date_range;
+use crate::tracker::IDLE_CATEGORY;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Bucket {
+    /// 24 buckets, 0 is midnight to 1 am local time.
+    HourOfDay,
+    /// 7 buckets, 0 is Monday, with days starting at the boundary's hour.
+    Weekday(DayBoundary),
+}
+
+impl Bucket {
+    fn len(self) -> usize {
+        match self {
+            Bucket::HourOfDay => 24,
+            Bucket::Weekday(_) => 7,
+        }
+    }
+
+    fn index<Tz: TimeZone>(self, tz: &Tz, timestamp: u64, time: &DateTime<Tz>) -> usize {
+        match self {
+            Bucket::HourOfDay => time.hour() as usize,
+            Bucket::Weekday(boundary) => {
+                boundary.date_of(tz, timestamp).weekday().num_days_from_monday() as usize
+            }
+        }
+    }
+}
+
+/// Seconds per category and bucket for `(category, start, end)` rows, with
+/// bucket boundaries taken in `tz`.
+pub fn distribute<Tz: TimeZone>(
+    tz: &Tz,
+    rows: &[(String, u64, u64)],
+    bucket: Bucket,
+) -> HashMap<String, Vec<u64>> {
+    let mut totals: HashMap<String, Vec<u64>> = HashMap::new();
+
+    for (category, start, end) in rows {
+        let buckets = totals
+            .entry(category.clone())
+            .or_insert_with(|| vec![0; bucket.len()]);
+
+        let mut t = *start;
+        while t < *end {
+            let Some(local) = tz.timestamp_opt(t as i64, 0).single() else {
+                break;
+            };
+            // Day and weekday boundaries are hour boundaries too, so walking
+            // hour by hour works for both
+            let into_hour = u64::from(local.minute() * 60 + local.second());
+            let next = (t + 3600 - into_hour).min(*end);
+
+            buckets[bucket.index(tz, t, &local)] += next - t;
+            t = next;
+        }
+    }
+
+    totals
+}
+
+fn load_rows(conn: &Connection, from: NaiveDate, to: NaiveDate, focused_only: bool) -> Result<Vec<(String, u64, u64)>> {
+    let mut stmt = conn.prepare_cached(
+        "SELECT category, start_time, end_time FROM app_usage
+         WHERE date BETWEEN ?1 AND ?2 AND category != ?3 AND (NOT ?4 OR is_focused)",
+    )?;
+
+    let rows = stmt.query_map(
+        (from.to_string(), to.to_string(), IDLE_CATEGORY, focused_only),
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+    )?;
+    rows.collect()
+}
+
+/// Seconds per category and bucket between `from` and `to`, in local time.
+pub fn distribution(
+    conn: &Connection,
+    from: NaiveDate,
+    to: NaiveDate,
+    focused_only: bool,
+    bucket: Bucket,
+) -> Result<HashMap<String, Vec<u64>>> {
+    Ok(distribute(&Local, &load_rows(conn, from, to, focused_only)?, bucket))
+}
+
+
+// --------------- Commands --------------- //
+
+/// Per category, 24 totals in seconds starting at midnight.
+#[tauri::command]
+pub fn get_hourly_distribution(
+    db: State<'_, Db>,
+    from: String,
+    to: String,
+    focused_only: Option<bool>,
+) -> Result<HashMap<String, Vec<u64>>, DeskflowError> {
+    let (from, to) = parse_date_range(&from, &to)?;
+    Ok(distribution(&db.lock(), from, to, focused_only.unwrap_or(true), Bucket::HourOfDay)?)
+}
+
+/// Per category, 7 totals in seconds starting on Monday. Days start at the
+/// configured day start hour.
+#[tauri::command]
+pub fn get_weekday_distribution(
+    db: State<'_, Db>,
+    from: String,
+    to: String,
+    focused_only: Option<bool>,
+) -> Result<HashMap<String, Vec<u64>>, DeskflowError> {
+    let (from, to) = parse_date_range(&from, &to)?;
+    let conn = db.lock();
+    let bucket = Bucket::Weekday(DayBoundary::load(&conn)?);
+    Ok(distribution(&conn, from, to, focused_only.unwrap_or(true), bucket)?)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use chrono_tz::America::New_York;
+
+    fn utc(d: u32, h: u32, min: u32) -> u64 {
+        Utc.with_ymd_and_hms(2024, 5, d, h, min, 0).unwrap().timestamp() as u64
+    }
+
+    fn new_york(m: u32, d: u32, h: u32, min: u32) -> u64 {
+        New_York.with_ymd_and_hms(2024, m, d, h, min, 0).earliest().unwrap().timestamp() as u64
+    }
+
+    fn rows(start: u64, end: u64) -> Vec<(String, u64, u64)> {
+        vec![("Work".to_string(), start, end)]
+    }
+
+    /// Non-zero buckets as `(index, minutes)`.
+    fn minutes(totals: &HashMap<String, Vec<u64>>) -> Vec<(usize, u64)> {
+        totals["Work"]
+            .iter()
+            .enumerate()
+            .filter(|(_, seconds)| **seconds > 0)
+            .map(|(index, seconds)| (index, seconds / 60))
+            .collect()
+    }
+
+    #[test]
+    fn splits_at_hour_boundaries() {
+        let totals = distribute(&Utc, &rows(utc(6, 9, 50), utc(6, 12, 20)), Bucket::HourOfDay);
+
+        assert_eq!(minutes(&totals), vec![(9, 10), (10, 60), (11, 60), (12, 20)]);
+    }
+
+    #[test]
+    fn splits_across_midnight_into_the_next_weekday() {
+        // Friday 23:30 to Saturday 00:45
+        let session = rows(utc(10, 23, 30), utc(11, 0, 45));
+
+        let hours = distribute(&Utc, &session, Bucket::HourOfDay);
+        assert_eq!(minutes(&hours), vec![(0, 45), (23, 30)]);
+
+        let weekdays = distribute(&Utc, &session, Bucket::Weekday(DayBoundary::default()));
+        assert_eq!(minutes(&weekdays), vec![(4, 30), (5, 45)]);
+    }
+
+    #[test]
+    fn weekdays_follow_the_day_start() {
+        // Saturday 01:00 to 05:00, with days starting at 4 am
+        let session = rows(utc(11, 1, 0), utc(11, 5, 0));
+
+        let weekdays = distribute(&Utc, &session, Bucket::Weekday(DayBoundary { start_hour: 4 }));
+        assert_eq!(minutes(&weekdays), vec![(4, 180), (5, 60)]);
+    }
+
+    #[test]
+    fn counts_real_time_on_dst_days() {
+        // 01:30 EST to 03:30 EDT is one hour; 2:00 to 3:00 never happens
+        let spring = rows(new_york(3, 10, 1, 30), new_york(3, 10, 3, 30));
+        let spring = distribute(&New_York, &spring, Bucket::HourOfDay);
+        assert_eq!(minutes(&spring), vec![(1, 30), (3, 30)]);
+
+        // 00:30 EDT to 02:30 EST is three hours, two of them in the 1 am hour
+        let fall = rows(new_york(11, 3, 0, 30), new_york(11, 3, 2, 30));
+        let fall = distribute(&New_York, &fall, Bucket::HourOfDay);
+        assert_eq!(minutes(&fall), vec![(0, 30), (1, 120), (2, 30)]);
+    }
+}
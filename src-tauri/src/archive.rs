@@ -0,0 +1,647 @@
+// --------------------------------------------------------------------------
+// Full backup / machine migration as a JSON Lines archive. The first line is
+// a header naming the archive format version and the schema version of the
+// database it came from; every following line is one record tagged with its
+// `type`: a categorization rule, a setting, a productivity score, a daily
+// roll-up row or a session. Settings include the contents of
+// `settings.toml`. Bookkeeping such as the last retention run is left out,
+// the importing machine keeps its own.
+//
+// Importing either merges into the existing data, skipping sessions that
+// overlap one already stored and anything else that already exists, or
+// replaces it wholesale. Either way the database part is one transaction;
+// `settings.toml` is only replaced once it has been committed.
+// --------------------------------------------------------------------------
+
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+use chrono::{Duration, Local};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, State};
+
+use crate::days::{DayBoundary, START_HOUR_KEY};
+use crate::db::{get_state, insert_session, set_state, AppSession, Db};
+use crate::error::DeskflowError;
+use crate::migrations;
+use crate::rules::{insert_rule, load_rules, ActiveRules, Categorizer, Rule};
+use crate::scoring::CONFIG_KEY;
+use crate::settings::{self, announce, ActiveSettings, Settings};
+
+const FORMAT: &str = "deskflow-archive";
+
+/// Bump when the record layout changes in a way older builds can't read.
+pub const ARCHIVE_VERSION: u32 = 1;
+
+/// `app_state` keys that are user settings rather than bookkeeping.
+const SETTING_KEYS: &[&str] = &[CONFIG_KEY, START_HOUR_KEY];
+
+/// Key of the setting record holding `settings.toml`.
+const SETTINGS_FILE_KEY: &str = "settings.toml";
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Header {
+    pub format: String,
+    pub version: u32,
+    pub schema_version: u32,
+    pub exported_at: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DailyUsage {
+    pub date: String,
+    pub app_name: String,
+    pub category: String,
+    pub focused_seconds: u64,
+    pub background_seconds: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Score {
+    pub date: String,
+    pub score: f64,
+    pub model: Option<String>,
+    pub model_version: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Record {
+    Header(Header),
+    Rule(Rule),
+    Setting { key: String, value: String },
+    Score(Score),
+    DailyUsage(DailyUsage),
+    Session(AppSession),
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportMode {
+    /// Keep what is there and add what is missing.
+    Merge,
+    /// Delete sessions, roll-ups, scores, rules and settings first.
+    Replace,
+}
+
+#[derive(Serialize, Debug, Default)]
+pub struct ImportReport {
+    pub sessions_imported: u64,
+    /// Sessions left out because they overlap one already stored.
+    pub sessions_skipped: u64,
+    pub daily_rows: u64,
+    pub scores: u64,
+    pub rules: u64,
+    pub settings: u64,
+}
+
+
+// --------------- Export --------------- //
+
+fn write_record(out: &mut impl Write, record: &Record) -> Result<(), DeskflowError> {
+    serde_json::to_writer(&mut *out, record)?;
+    out.write_all(b"\n")?;
+    Ok(())
+}
+
+/// Write the whole database and `settings` to `out`. Returns the number of
+/// records written, not counting the header.
+///
+/// Everything is read in one transaction, so the tracker writing in between
+/// can't leave the archive with a score for sessions it doesn't contain.
+pub fn export(conn: &Connection, settings: &Settings, out: &mut impl Write) -> Result<u64, DeskflowError> {
+    let tx = conn.unchecked_transaction()?;
+    let written = write_records(&tx, settings, out)?;
+    tx.commit()?;
+    Ok(written)
+}
+
+fn write_records(conn: &Connection, settings: &Settings, out: &mut impl Write) -> Result<u64, DeskflowError> {
+    let mut written = 0;
+
+    write_record(
+        out,
+        &Record::Header(Header {
+            format: FORMAT.to_string(),
+            version: ARCHIVE_VERSION,
+            schema_version: migrations::current_version(conn)?,
+            exported_at: Local::now().to_rfc3339(),
+        }),
+    )?;
+
+    for rule in load_rules(conn)? {
+        write_record(out, &Record::Rule(rule))?;
+        written += 1;
+    }
+
+    for key in SETTING_KEYS {
+        if let Some(value) = get_state(conn, key)? {
+            write_record(
+                out,
+                &Record::Setting {
+                    key: key.to_string(),
+                    value,
+                },
+            )?;
+            written += 1;
+        }
+    }
+
+    write_record(
+        out,
+        &Record::Setting {
+            key: SETTINGS_FILE_KEY.to_string(),
+            value: settings::to_toml(settings)?,
+        },
+    )?;
+    written += 1;
+
+    let mut stmt = conn.prepare("SELECT date, score, model, model_version FROM productivity_scores ORDER BY date")?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        let score = Score {
+            date: row.get(0)?,
+            score: row.get(1)?,
+            model: row.get(2)?,
+            model_version: row.get(3)?,
+        };
+        write_record(out, &Record::Score(score))?;
+        written += 1;
+    }
+
+    let mut stmt = conn.prepare(
+        "SELECT date, app_name, category, focused_seconds, background_seconds
+         FROM daily_app_usage ORDER BY date, app_name, category",
+    )?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        let usage = DailyUsage {
+            date: row.get(0)?,
+            app_name: row.get(1)?,
+            category: row.get(2)?,
+            focused_seconds: row.get(3)?,
+            background_seconds: row.get(4)?,
+        };
+        write_record(out, &Record::DailyUsage(usage))?;
+        written += 1;
+    }
+
+    let mut stmt = conn.prepare(
+        "SELECT app_name, window_title, category, start_time, end_time, is_focused, rule_id
+         FROM app_usage ORDER BY start_time, id",
+    )?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        let session = AppSession {
+            app_name: row.get(0)?,
+            window_title: row.get(1)?,
+            category: row.get(2)?,
+            start_time: row.get(3)?,
+            end_time: row.get(4)?,
+            is_focused: row.get(5)?,
+            rule_id: row.get(6)?,
+        };
+        write_record(out, &Record::Session(session))?;
+        written += 1;
+    }
+
+    out.flush()?;
+    Ok(written)
+}
+
+
+// --------------- Import --------------- //
+
+fn check_header(header: &Header) -> Result<(), DeskflowError> {
+    if header.format != FORMAT {
+        return Err(DeskflowError::config(format!("not a DeskFlow archive ({})", header.format)));
+    }
+    if header.version > ARCHIVE_VERSION {
+        return Err(DeskflowError::config(format!(
+            "archive version {} is newer than this build supports ({})",
+            header.version, ARCHIVE_VERSION
+        )));
+    }
+    let latest = migrations::latest_version();
+    if header.schema_version > latest {
+        return Err(DeskflowError::config(format!(
+            "archive comes from database schema version {}, this build supports up to {}",
+            header.schema_version, latest
+        )));
+    }
+    Ok(())
+}
+
+/// Whether a stored session of the same window and focus overlaps `session`.
+fn overlaps_stored(conn: &Connection, boundary: &DayBoundary, session: &AppSession) -> rusqlite::Result<bool> {
+    // Only look at the days around the session; the day start may differ
+    // from the machine the archive came from
+    let first_day = boundary.date_of(&Local, session.start_time) - Duration::days(1);
+    let last_day = boundary.date_of(&Local, session.end_time) + Duration::days(1);
+
+    conn.prepare_cached(
+        "SELECT 1 FROM app_usage
+         WHERE date BETWEEN ?1 AND ?2 AND app_name = ?3 AND window_title = ?4 AND is_focused = ?5
+           AND ((start_time < ?7 AND end_time > ?6) OR (start_time = ?6 AND end_time = ?7))",
+    )?
+    .exists(params![
+        first_day.to_string(),
+        last_day.to_string(),
+        session.app_name,
+        session.window_title,
+        session.is_focused,
+        session.start_time,
+        session.end_time
+    ])
+}
+
+fn clear(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute_batch(
+        "DELETE FROM app_usage;
+         DELETE FROM daily_app_usage;
+         DELETE FROM productivity_scores;
+         DELETE FROM categorization_rules;",
+    )?;
+    for key in SETTING_KEYS {
+        conn.execute("DELETE FROM app_state WHERE key = ?1", [key])?;
+    }
+    Ok(())
+}
+
+fn import_record(
+    conn: &Connection,
+    boundary: &mut DayBoundary,
+    record: Record,
+    mode: ImportMode,
+    report: &mut ImportReport,
+    settings_file: &mut Option<Settings>,
+) -> Result<(), DeskflowError> {
+    match record {
+        Record::Header(_) => return Err(DeskflowError::config("unexpected second header")),
+        Record::Rule(rule) => {
+            let exists = conn
+                .query_row("SELECT 1 FROM categorization_rules WHERE id = ?1", [&rule.id], |_| Ok(()))
+                .optional()?
+                .is_some();
+            if !exists {
+                insert_rule(conn, &rule)?;
+                report.rules += 1;
+            }
+        }
+        Record::Setting { key, value } if key == SETTINGS_FILE_KEY => {
+            // This machine always has settings of its own; keep them unless
+            // replacing
+            if mode == ImportMode::Replace {
+                *settings_file = Some(settings::from_toml(&value).map_err(|e| e.context(SETTINGS_FILE_KEY))?);
+                report.settings += 1;
+            }
+        }
+        Record::Setting { key, value } => {
+            if !SETTING_KEYS.contains(&key.as_str()) {
+                return Ok(()); // from a newer build; nothing here reads it
+            }
+            if mode == ImportMode::Replace || get_state(conn, &key)?.is_none() {
+                set_state(conn, &key, &value)?;
+                *boundary = DayBoundary::load(conn)?;
+                report.settings += 1;
+            }
+        }
+        Record::Score(score) => {
+            report.scores += conn
+                .execute(
+                    "INSERT OR IGNORE INTO productivity_scores (date, score, model, model_version)
+                     VALUES (?1, ?2, ?3, ?4)",
+                    params![score.date, score.score, score.model, score.model_version],
+                )? as u64;
+        }
+        Record::DailyUsage(usage) => {
+            report.daily_rows += conn
+                .execute(
+                    "INSERT OR IGNORE INTO daily_app_usage
+                        (date, app_name, category, focused_seconds, background_seconds)
+                     VALUES (?1, ?2, ?3, ?4, ?5)",
+                    params![
+                        usage.date,
+                        usage.app_name,
+                        usage.category,
+                        usage.focused_seconds,
+                        usage.background_seconds
+                    ],
+                )? as u64;
+        }
+        Record::Session(session) => {
+            if mode == ImportMode::Merge && overlaps_stored(conn, boundary, &session)? {
+                report.sessions_skipped += 1;
+            } else {
+                insert_session(conn, boundary, &session)?;
+                report.sessions_imported += 1;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Read an archive from `input` into the database. Returns what was
+/// imported, the categorizer for the resulting rule set and, when replacing,
+/// the archived `settings.toml` for the caller to apply; nothing is written
+/// if any line fails or the rules don't compile.
+pub fn import(
+    conn: &mut Connection,
+    input: impl BufRead,
+    mode: ImportMode,
+) -> Result<(ImportReport, Categorizer, Option<Settings>), DeskflowError> {
+    let mut lines = input
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| line.as_ref().map_or(true, |l| !l.trim().is_empty()));
+    let parse = |(index, line): (usize, std::io::Result<String>)| {
+        line.map_err(DeskflowError::from)
+            .and_then(|line| Ok(serde_json::from_str::<Record>(&line)?))
+            .map_err(|e| e.context(format_args!("line {}", index + 1)))
+    };
+
+    match lines.next().map(parse).transpose()? {
+        Some(Record::Header(header)) => check_header(&header)?,
+        _ => return Err(DeskflowError::config("archive does not start with a header")),
+    }
+
+    let tx = conn.transaction()?;
+    if mode == ImportMode::Replace {
+        clear(&tx)?;
+    }
+
+    let mut boundary = DayBoundary::load(&tx)?;
+    let mut report = ImportReport::default();
+    let mut settings_file = None;
+    for line in lines {
+        let index = line.0;
+        import_record(&tx, &mut boundary, parse(line)?, mode, &mut report, &mut settings_file)
+            .map_err(|e| e.context(format_args!("line {}", index + 1)))?;
+    }
+
+    let categorizer = Categorizer::new(&load_rules(&tx)?)?;
+    tx.commit()?;
+    Ok((report, categorizer, settings_file))
+}
+
+
+// --------------- Commands --------------- //
+
+/// Write the whole database and the settings to a JSON Lines archive at
+/// `path`.
+#[tauri::command]
+pub fn export_archive(
+    db: State<'_, Db>,
+    settings: State<'_, ActiveSettings>,
+    path: String,
+) -> Result<u64, DeskflowError> {
+    let conn = db.open_read_only()?;
+    let file = File::create(&path).map_err(|e| DeskflowError::io_at(Path::new(&path), e))?;
+    export(&conn, &settings.current(), &mut BufWriter::new(file))
+}
+
+/// Load the archive at `path`, merging it into or replacing the current data.
+#[tauri::command]
+pub fn import_archive(
+    app: AppHandle,
+    db: State<'_, Db>,
+    active: State<'_, ActiveRules>,
+    settings: State<'_, ActiveSettings>,
+    path: String,
+    mode: ImportMode,
+) -> Result<ImportReport, DeskflowError> {
+    let file = File::open(Path::new(&path)).map_err(|e| DeskflowError::io_at(Path::new(&path), e))?;
+    let (report, categorizer, settings_file) = import(&mut db.lock(), BufReader::new(file), mode)?;
+    active.replace(categorizer);
+    if let Some(imported) = settings_file {
+        settings.replace(imported)?;
+    }
+    if report.settings > 0 {
+        announce(&app, &settings);
+    }
+    Ok(report)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn database() -> Connection {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrations::migrate(&mut conn, Path::new(":memory:")).unwrap();
+        conn
+    }
+
+    fn session(title: &str, start: u64, end: u64) -> AppSession {
+        AppSession {
+            app_name: "code".to_string(),
+            window_title: title.to_string(),
+            category: "Work".to_string(),
+            start_time: start,
+            end_time: end,
+            is_focused: true,
+            rule_id: Some("vscode".to_string()),
+        }
+    }
+
+    /// A database with a bit of everything an archive carries.
+    fn populated() -> Connection {
+        let mut conn = database();
+        let base = 1_714_989_600; // 2024-05-06 10:00 UTC
+        crate::db::save_sessions(
+            &mut conn,
+            &[session("main.rs", base, base + 60), session("lib.rs", base + 60, base + 300)],
+        )
+        .unwrap();
+        for rule in crate::rules::default_rules() {
+            insert_rule(&conn, &rule).unwrap();
+        }
+        crate::db::store_productivity_score(&conn, "2024-05-05", 82.5, "weighted", 1).unwrap();
+        conn.execute(
+            "INSERT INTO daily_app_usage (date, app_name, category, focused_seconds, background_seconds)
+             VALUES ('2024-01-02', 'code', 'Work', 3600, 120)",
+            [],
+        )
+        .unwrap();
+        set_state(&conn, START_HOUR_KEY, "4").unwrap();
+        conn
+    }
+
+    fn archive_of(conn: &Connection) -> Vec<u8> {
+        let mut archive = Vec::new();
+        export(conn, &Settings::default(), &mut archive).unwrap();
+        archive
+    }
+
+    fn count(conn: &Connection, table: &str) -> u64 {
+        conn.query_row(&format!("SELECT COUNT(*) FROM {table}"), [], |row| row.get(0))
+            .unwrap()
+    }
+
+    /// Row counts of every table an import writes to.
+    fn counts(conn: &Connection) -> Vec<u64> {
+        ["app_usage", "daily_app_usage", "productivity_scores", "categorization_rules", "app_state"]
+            .iter()
+            .map(|table| count(conn, table))
+            .collect()
+    }
+
+    fn sessions(conn: &Connection) -> Vec<(String, u64, u64, Option<String>)> {
+        conn.prepare("SELECT window_title, start_time, end_time, rule_id FROM app_usage ORDER BY start_time")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap()
+    }
+
+    fn lines(archive: &[u8]) -> Vec<String> {
+        String::from_utf8(archive.to_vec()).unwrap().lines().map(String::from).collect()
+    }
+
+    #[test]
+    fn round_trip_into_a_fresh_database() {
+        let source = populated();
+        let archive = archive_of(&source);
+
+        let mut target = database();
+        let (report, categorizer, _) = import(&mut target, archive.as_slice(), ImportMode::Merge).unwrap();
+
+        assert_eq!(report.sessions_imported, 2);
+        assert_eq!(report.sessions_skipped, 0);
+        assert_eq!(report.daily_rows, 1);
+        assert_eq!(report.scores, 1);
+        assert_eq!(report.rules as usize, crate::rules::default_rules().len());
+        assert_eq!(sessions(&target), sessions(&source));
+        assert_eq!(get_state(&target, START_HOUR_KEY).unwrap().as_deref(), Some("4"));
+        assert_eq!(categorizer.categorize("main.rs", "code").rule_id.as_deref(), Some("vscode"));
+
+        // What comes back out is the same archive, bar the export time
+        let again = archive_of(&target);
+        assert_eq!(lines(&again)[1..], lines(&archive)[1..]);
+    }
+
+    #[test]
+    fn merging_twice_adds_nothing_the_second_time() {
+        let archive = archive_of(&populated());
+        let mut target = database();
+
+        import(&mut target, archive.as_slice(), ImportMode::Merge).unwrap();
+        let after_first = counts(&target);
+        let (report, _, _) = import(&mut target, archive.as_slice(), ImportMode::Merge).unwrap();
+
+        assert_eq!(counts(&target), after_first);
+        assert_eq!(report.sessions_imported, 0);
+        assert_eq!(report.sessions_skipped, 2);
+        assert_eq!((report.daily_rows, report.scores, report.rules, report.settings), (0, 0, 0, 0));
+    }
+
+    #[test]
+    fn merging_skips_overlapping_sessions() {
+        let archive = archive_of(&populated());
+        let mut target = database();
+        let base = 1_714_989_600;
+        // Same window, partly overlapping the archived "main.rs" session
+        crate::db::save_sessions(&mut target, &[session("main.rs", base + 30, base + 90)]).unwrap();
+
+        let (report, _, _) = import(&mut target, archive.as_slice(), ImportMode::Merge).unwrap();
+
+        assert_eq!((report.sessions_imported, report.sessions_skipped), (1, 1));
+        assert_eq!(count(&target, "app_usage"), 2);
+    }
+
+    #[test]
+    fn replacing_drops_what_was_there() {
+        let archive = archive_of(&populated());
+        let mut target = database();
+        crate::db::save_sessions(&mut target, &[session("notes.txt", 1_000_000, 1_000_100)]).unwrap();
+        crate::db::store_productivity_score(&target, "2020-01-01", 10.0, "ratio", 1).unwrap();
+        set_state(&target, START_HOUR_KEY, "6").unwrap();
+
+        import(&mut target, archive.as_slice(), ImportMode::Replace).unwrap();
+
+        let titles: Vec<_> = sessions(&target).into_iter().map(|s| s.0).collect();
+        assert_eq!(titles, vec!["main.rs", "lib.rs"]);
+        assert_eq!(count(&target, "productivity_scores"), 1);
+        assert_eq!(get_state(&target, START_HOUR_KEY).unwrap().as_deref(), Some("4"));
+    }
+
+    /// Import `archive` over a populated database in both modes and check
+    /// it fails with a config error and leaves everything as it was.
+    fn assert_rejected(archive: &[u8], reason: &str) {
+        for mode in [ImportMode::Merge, ImportMode::Replace] {
+            let mut target = populated();
+            let before = (counts(&target), sessions(&target));
+
+            let Err(err) = import(&mut target, archive, mode) else {
+                panic!("{reason}: archive was imported");
+            };
+            assert_eq!(err.kind(), "config", "{reason}: {err}");
+            assert_eq!((counts(&target), sessions(&target)), before, "{reason}");
+        }
+    }
+
+    #[test]
+    fn bad_archives_are_rejected_without_writes() {
+        let archive = lines(&archive_of(&populated()));
+        let body = archive[1..].join("\n");
+        let with_header = |header: serde_json::Value| format!("{}\n{}", header, body).into_bytes();
+        let header = |format: &str, version: u32, schema_version: u32| {
+            serde_json::json!({
+                "type": "header",
+                "format": format,
+                "version": version,
+                "schema_version": schema_version,
+                "exported_at": "2024-05-06T12:00:00+00:00",
+            })
+        };
+        let latest = migrations::latest_version();
+
+        assert_rejected(body.as_bytes(), "missing header");
+        assert_rejected(&with_header(header("something-else", ARCHIVE_VERSION, latest)), "wrong format");
+        assert_rejected(&with_header(header(FORMAT, ARCHIVE_VERSION + 1, latest)), "newer archive version");
+        assert_rejected(&with_header(header(FORMAT, ARCHIVE_VERSION, latest + 1)), "newer schema version");
+
+        // A broken line at the end undoes everything before it
+        let truncated = format!("{}\n{{\"type\": \"session\"", archive.join("\n"));
+        assert_rejected(truncated.as_bytes(), "broken last line");
+    }
+
+    fn archive_with(settings: &Settings) -> Vec<u8> {
+        let mut archive = Vec::new();
+        export(&database(), settings, &mut archive).unwrap();
+        archive
+    }
+
+    #[test]
+    fn settings_file_travels_with_the_archive() {
+        let mut settings = Settings::default();
+        settings.history.raw_days = 30;
+        settings.hud.width = 640.0;
+        let archive = archive_with(&settings);
+
+        let (report, _, imported) = import(&mut database(), archive.as_slice(), ImportMode::Replace).unwrap();
+        assert_eq!(imported, Some(settings));
+        assert_eq!(report.settings, 1);
+
+        // Merging keeps this machine's settings
+        let (_, _, imported) = import(&mut database(), archive.as_slice(), ImportMode::Merge).unwrap();
+        assert_eq!(imported, None);
+    }
+
+    #[test]
+    fn invalid_settings_file_fails_the_import() {
+        let mut settings = Settings::default();
+        settings.history.raw_days = 0;
+        let archive = archive_with(&settings);
+
+        let Err(err) = import(&mut database(), archive.as_slice(), ImportMode::Replace) else {
+            panic!("an invalid settings.toml was imported");
+        };
+        assert_eq!(err.kind(), "config");
+        assert!(err.to_string().contains(SETTINGS_FILE_KEY), "{err}");
+    }
+}
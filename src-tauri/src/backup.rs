@@ -0,0 +1,350 @@
+// --------------------------------------------------------------------------
+// Rotating daily snapshots of usage_data.db, taken with SQLite's online
+// backup API so the tracker can keep writing meanwhile. Snapshots sit next
+// to the database as `usage_data.db.<date>.bak`; only the newest
+// `BackupPolicy::keep` are kept.
+//
+// Restoring copies a snapshot back into the open database (again through
+// the backup API, so nothing has to be renamed while it is in use), after
+// checking it with `PRAGMA integrity_check` and setting the current state
+// aside as `usage_data.db.pre-restore.bak`.
+// --------------------------------------------------------------------------
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chrono::NaiveDate;
+use rusqlite::backup::{Backup, Progress, StepResult};
+use rusqlite::{ffi, Connection, DatabaseName, OpenFlags};
+use serde::Serialize;
+use tauri::State;
+
+use crate::db::Db;
+use crate::error::DeskflowError;
+use crate::migrations;
+use crate::rules::{load_rules, ActiveRules, Categorizer};
+
+#[derive(Clone, Debug)]
+pub struct BackupPolicy {
+    /// Number of daily snapshots kept; 0 turns backups off.
+    pub keep: usize,
+}
+
+impl Default for BackupPolicy {
+    fn default() -> Self {
+        Self { keep: 7 }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct BackupInfo {
+    pub name: String,
+    pub date: String,
+    pub size_bytes: u64,
+}
+
+/// `usage_data.db` → `usage_data.db.<date>.bak`
+fn backup_path(db_path: &Path, date: NaiveDate) -> PathBuf {
+    let mut name = db_path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".{}.bak", date));
+    db_path.with_file_name(name)
+}
+
+/// Daily snapshots of `db_path`, newest first.
+pub fn list_backups(db_path: &Path) -> Vec<BackupInfo> {
+    let db_name = db_path.file_name().unwrap_or_default().to_string_lossy().to_string();
+    let Some(dir) = db_path.parent() else {
+        return Vec::new();
+    };
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+
+    let mut backups: Vec<BackupInfo> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let date = name.strip_prefix(&db_name)?.strip_prefix('.')?.strip_suffix(".bak")?;
+            NaiveDate::parse_from_str(date, "%Y-%m-%d").ok()?;
+
+            Some(BackupInfo {
+                date: date.to_string(),
+                size_bytes: entry.metadata().map(|m| m.len()).unwrap_or(0),
+                name,
+            })
+        })
+        .collect();
+
+    backups.sort_by(|a, b| b.date.cmp(&a.date));
+    backups
+}
+
+fn sqlite_failure(code: std::os::raw::c_int, message: String) -> DeskflowError {
+    rusqlite::Error::SqliteFailure(ffi::Error::new(code), Some(message)).into()
+}
+
+/// Copy the database at `db_path` to `dest` in one consistent snapshot.
+fn snapshot(db_path: &Path, dest: &Path) -> Result<(), DeskflowError> {
+    let src = Connection::open_with_flags(db_path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    src.busy_timeout(std::time::Duration::from_secs(5))?;
+
+    // Write next to the target first so a crash never leaves half a backup
+    let mut partial = dest.as_os_str().to_os_string();
+    partial.push(".partial");
+    let partial = PathBuf::from(partial);
+    let _ = fs::remove_file(&partial);
+
+    {
+        let mut dst = Connection::open(&partial)?;
+        let backup = Backup::new(&src, &mut dst)?;
+        // One step copies every page under a single read transaction
+        if backup.step(-1)? != StepResult::Done {
+            return Err(sqlite_failure(ffi::SQLITE_BUSY, "database stayed busy during backup".to_string()));
+        }
+    }
+
+    fs::rename(&partial, dest).map_err(|e| DeskflowError::io_at(dest, e))
+}
+
+/// Take today's snapshot unless it exists, then drop all but the newest
+/// `policy.keep`.
+pub fn backup_if_new_day(db_path: &Path, policy: &BackupPolicy, today: NaiveDate) -> Result<(), DeskflowError> {
+    if policy.keep == 0 {
+        return Ok(());
+    }
+
+    let dest = backup_path(db_path, today);
+    if !dest.exists() {
+        snapshot(db_path, &dest)?;
+    }
+
+    for old in list_backups(db_path).into_iter().skip(policy.keep) {
+        let _ = fs::remove_file(db_path.with_file_name(old.name));
+    }
+    Ok(())
+}
+
+/// Check `path` with `PRAGMA integrity_check` and make sure this build can
+/// open its schema.
+fn verify(path: &Path) -> Result<(), DeskflowError> {
+    let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+
+    let problems: Vec<String> = conn
+        .prepare("PRAGMA integrity_check")
+        .and_then(|mut stmt| stmt.query_map([], |row| row.get(0))?.collect())?;
+    if problems != ["ok"] {
+        return Err(sqlite_failure(ffi::SQLITE_CORRUPT, format!("backup is corrupt: {}", problems.join("; "))));
+    }
+
+    let version = migrations::current_version(&conn)?;
+    if version > migrations::latest_version() {
+        return Err(DeskflowError::config(format!(
+            "backup comes from a newer build (schema version {})",
+            version
+        )));
+    }
+    Ok(())
+}
+
+/// Replace the contents of the open database `conn` with the backup at
+/// `backup`, then bring it up to the current schema.
+pub fn restore(conn: &mut Connection, db_path: &Path, backup: &Path) -> Result<(), DeskflowError> {
+    verify(backup)?;
+
+    let mut safety = db_path.as_os_str().to_os_string();
+    safety.push(".pre-restore.bak");
+    snapshot(db_path, Path::new(&safety)).map_err(|e| e.context("could not save the current database"))?;
+
+    conn.restore(DatabaseName::Main, backup, None::<fn(Progress)>)?;
+    migrations::migrate(conn, db_path)?;
+    Ok(())
+}
+
+
+// --------------- Commands --------------- //
+
+#[tauri::command]
+pub fn get_backups(db: State<'_, Db>) -> Vec<BackupInfo> {
+    list_backups(db.path())
+}
+
+/// Restore the snapshot called `name` (as listed by `get_backups`).
+#[tauri::command]
+pub fn restore_backup(db: State<'_, Db>, active: State<'_, ActiveRules>, name: String) -> Result<(), DeskflowError> {
+    let db_path = db.path().to_path_buf();
+    if !list_backups(&db_path).iter().any(|b| b.name == name) {
+        return Err(DeskflowError::config(format!("no backup named '{}'", name)));
+    }
+
+    let mut conn = db.lock();
+    restore(&mut conn, &db_path, &db_path.with_file_name(&name))?;
+
+    // The restored database may come with other rules
+    let rules = load_rules(&conn)?;
+    active.replace(Categorizer::new(&rules)?);
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn day(d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 5, d).unwrap()
+    }
+
+    fn database() -> (TempDir, Db) {
+        let dir = TempDir::new().unwrap();
+        let db = Db::open(&dir.path().join("usage_data.db")).unwrap();
+        (dir, db)
+    }
+
+    fn add_row(conn: &Connection, title: &str) {
+        conn.execute(
+            "INSERT INTO app_usage (app_name, window_title, category, start_time, end_time, date, is_focused)
+             VALUES ('code', ?1, 'Work', 0, 5, '2024-05-06', 1)",
+            [title],
+        )
+        .unwrap();
+    }
+
+    fn titles(conn: &Connection) -> Vec<String> {
+        conn.prepare("SELECT window_title FROM app_usage ORDER BY id")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn keeps_only_the_newest_backups() {
+        let (_dir, db) = database();
+        let policy = BackupPolicy { keep: 3 };
+
+        for d in 6..=9 {
+            backup_if_new_day(db.path(), &policy, day(d)).unwrap();
+        }
+
+        let dates: Vec<String> = list_backups(db.path()).into_iter().map(|b| b.date).collect();
+        assert_eq!(dates, ["2024-05-09", "2024-05-08", "2024-05-07"]);
+        assert!(!backup_path(db.path(), day(6)).exists());
+    }
+
+    #[test]
+    fn one_backup_per_day() {
+        let (_dir, db) = database();
+        let policy = BackupPolicy::default();
+
+        backup_if_new_day(db.path(), &policy, day(6)).unwrap();
+        add_row(&db.lock(), "after the backup");
+        backup_if_new_day(db.path(), &policy, day(6)).unwrap();
+
+        let backup = Connection::open(backup_path(db.path(), day(6))).unwrap();
+        assert!(titles(&backup).is_empty());
+        assert_eq!(list_backups(db.path()).len(), 1);
+    }
+
+    #[test]
+    fn keeping_none_turns_backups_off() {
+        let (_dir, db) = database();
+        backup_if_new_day(db.path(), &BackupPolicy { keep: 0 }, day(6)).unwrap();
+        assert!(list_backups(db.path()).is_empty());
+    }
+
+    #[test]
+    fn corrupt_backups_fail_verification() {
+        let (dir, db) = database();
+        backup_if_new_day(db.path(), &BackupPolicy::default(), day(6)).unwrap();
+        let good = backup_path(db.path(), day(6));
+        verify(&good).unwrap();
+
+        let garbage = dir.path().join("garbage.bak");
+        fs::write(&garbage, b"this is not a database").unwrap();
+        assert!(verify(&garbage).is_err());
+
+        // A header that promises more pages than the file has
+        let truncated = dir.path().join("truncated.bak");
+        let bytes = fs::read(&good).unwrap();
+        fs::write(&truncated, &bytes[..bytes.len() / 2]).unwrap();
+        assert!(verify(&truncated).is_err());
+    }
+
+    #[test]
+    fn backups_from_newer_builds_fail_verification() {
+        let (dir, _db) = database();
+        let path = dir.path().join("newer.bak");
+        let conn = Connection::open(&path).unwrap();
+        conn.pragma_update(None, "user_version", migrations::latest_version() + 1)
+            .unwrap();
+        drop(conn);
+
+        let err = verify(&path).unwrap_err();
+        assert!(err.to_string().contains("newer build"), "{}", err);
+    }
+
+    #[test]
+    fn a_failed_restore_changes_nothing() {
+        let (dir, db) = database();
+        add_row(&db.lock(), "current");
+        let garbage = dir.path().join("usage_data.db.2024-05-06.bak");
+        fs::write(&garbage, b"this is not a database").unwrap();
+
+        assert!(restore(&mut db.lock(), db.path(), &garbage).is_err());
+        assert_eq!(titles(&db.lock()), ["current"]);
+    }
+
+    #[test]
+    fn restoring_replaces_the_data_and_keeps_a_safety_copy() {
+        let (_dir, db) = database();
+        add_row(&db.lock(), "backed up");
+        backup_if_new_day(db.path(), &BackupPolicy::default(), day(6)).unwrap();
+        add_row(&db.lock(), "after the backup");
+
+        restore(&mut db.lock(), db.path(), &backup_path(db.path(), day(6))).unwrap();
+
+        assert_eq!(titles(&db.lock()), ["backed up"]);
+        let safety = db.path().with_file_name("usage_data.db.pre-restore.bak");
+        assert_eq!(
+            titles(&Connection::open(safety).unwrap()),
+            ["backed up", "after the backup"]
+        );
+    }
+
+    #[test]
+    fn restoring_an_older_backup_migrates_it() {
+        let (dir, db) = database();
+
+        // A backup taken before rules were recorded with each session
+        let old = dir.path().join("usage_data.db.2024-05-06.bak");
+        let mut conn = Connection::open(&old).unwrap();
+        for migration in migrations::MIGRATIONS.iter().take_while(|m| m.version <= 3) {
+            let tx = conn.transaction().unwrap();
+            (migration.up)(&tx).unwrap();
+            tx.pragma_update(None, "user_version", migration.version).unwrap();
+            tx.commit().unwrap();
+        }
+        conn.execute(
+            "INSERT INTO app_usage (app_name, window_title, category, start_time, end_time, date, is_focused)
+             VALUES ('code', 'old.rs', 'Work', 0, 5, '2024-05-06', 1)",
+            [],
+        )
+        .unwrap();
+        drop(conn);
+
+        restore(&mut db.lock(), db.path(), &old).unwrap();
+
+        let conn = db.lock();
+        assert_eq!(
+            migrations::current_version(&conn).unwrap(),
+            migrations::latest_version()
+        );
+        assert_eq!(titles(&conn), ["old.rs"]);
+        let rule_id: Option<String> = conn
+            .query_row("SELECT rule_id FROM app_usage", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(rule_id, None);
+    }
+}
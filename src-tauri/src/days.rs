@@ -0,0 +1,239 @@
+// --------------------------------------------------------------------------
+// Which calendar day a moment belongs to. Days are local days that start at
+// `start_hour` (0 = midnight), so a night owl can have 1 am count toward the
+// evening before. Every `date` written to or looked up in the database goes
+// through here, and sessions that straddle a day start are split so each
+// piece lands on its own day. The hour is kept in `app_state`; changing it
+// only affects sessions stored from then on.
+// --------------------------------------------------------------------------
+
+use chrono::{DateTime, Duration, Local, LocalResult, NaiveDate, TimeZone};
+use rusqlite::{Connection, Result};
+use tauri::{AppHandle, State};
+
+use crate::db::{get_state, set_state, Db};
+use crate::error::DeskflowError;
+use crate::settings::{announce, ActiveSettings};
+
+pub const START_HOUR_KEY: &str = "day_start_hour";
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct DayBoundary {
+    /// Local hour, 0-23, at which a new day begins.
+    pub start_hour: u32,
+}
+
+impl DayBoundary {
+    pub fn load(conn: &Connection) -> Result<Self> {
+        let start_hour = get_state(conn, START_HOUR_KEY)?
+            .and_then(|hour| hour.parse().ok())
+            .filter(|hour| *hour < 24)
+            .unwrap_or(0);
+        Ok(Self { start_hour })
+    }
+
+    /// The day `timestamp` (Unix seconds) belongs to in `tz`.
+    pub fn date_of<Tz: TimeZone>(&self, tz: &Tz, timestamp: u64) -> NaiveDate {
+        let local = tz
+            .timestamp_opt(timestamp as i64, 0)
+            .single()
+            .map(|t| t.naive_local())
+            .unwrap_or_default();
+        (local - Duration::hours(i64::from(self.start_hour))).date()
+    }
+
+    /// Unix time at which `date` begins in `tz`. When the start hour falls
+    /// into a DST gap the day begins once the clocks have jumped; when it
+    /// occurs twice, at the first one.
+    pub fn start_of<Tz: TimeZone>(&self, tz: &Tz, date: NaiveDate) -> u64 {
+        let mut local = date.and_hms_opt(self.start_hour, 0, 0).unwrap_or_default();
+
+        // DST gaps are never longer than a couple of hours
+        for _ in 0..3 {
+            match tz.from_local_datetime(&local) {
+                LocalResult::Single(t) => return timestamp(&t),
+                // `Local` doesn't promise which of the two comes first
+                LocalResult::Ambiguous(a, b) => return timestamp(&a).min(timestamp(&b)),
+                LocalResult::None => local += Duration::hours(1),
+            }
+        }
+        timestamp(&tz.from_utc_datetime(&local))
+    }
+
+    /// Cut `start..end` at every day start, returning `(day, start, end)`
+    /// for each piece in order.
+    pub fn split<Tz: TimeZone>(&self, tz: &Tz, start: u64, end: u64) -> Vec<(NaiveDate, u64, u64)> {
+        let mut pieces = Vec::new();
+        let mut t = start;
+
+        loop {
+            let date = self.date_of(tz, t);
+            let next_day = self.start_of(tz, date + Duration::days(1));
+
+            if end <= next_day || next_day <= t {
+                pieces.push((date, t, end));
+                return pieces;
+            }
+            pieces.push((date, t, next_day));
+            t = next_day;
+        }
+    }
+
+    /// The day it is now, in local time.
+    pub fn today(&self) -> NaiveDate {
+        self.date_of(&Local, Local::now().timestamp().max(0) as u64)
+    }
+}
+
+fn timestamp<Tz: TimeZone>(time: &DateTime<Tz>) -> u64 {
+    time.timestamp().max(0) as u64
+}
+
+
+// --------------- Commands --------------- //
+
+#[tauri::command]
+pub fn get_day_start_hour(db: State<'_, Db>) -> Result<u32, DeskflowError> {
+    Ok(DayBoundary::load(&db.lock())?.start_hour)
+}
+
+#[tauri::command]
+pub fn set_day_start_hour(
+    app: AppHandle,
+    db: State<'_, Db>,
+    active: State<'_, ActiveSettings>,
+    hour: u32,
+) -> Result<(), DeskflowError> {
+    if hour > 23 {
+        return Err(DeskflowError::config(format!("{} is not an hour of the day", hour)));
+    }
+    set_state(&db.lock(), START_HOUR_KEY, &hour.to_string())?;
+    announce(&app, &active);
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{FixedOffset, Utc};
+    use chrono_tz::America::New_York;
+
+    /// Eastern Standard Time all year round, to compare against New York.
+    fn est() -> FixedOffset {
+        FixedOffset::west_opt(5 * 3600).unwrap()
+    }
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    fn utc(y: i32, m: u32, d: u32, h: u32, min: u32) -> u64 {
+        Utc.with_ymd_and_hms(y, m, d, h, min, 0).unwrap().timestamp() as u64
+    }
+
+    fn day_length<Tz: TimeZone>(boundary: DayBoundary, tz: &Tz, day: NaiveDate) -> u64 {
+        boundary.start_of(tz, day + Duration::days(1)) - boundary.start_of(tz, day)
+    }
+
+    // New York springs forward at 02:00 EST on 2024-03-10 and falls back at
+    // 02:00 EDT on 2024-11-03
+
+    #[test]
+    fn spring_forward_gap() {
+        let boundary = DayBoundary { start_hour: 2 };
+        let day = date(2024, 3, 10);
+
+        // 02:00 doesn't exist in New York; the day starts at 03:00 EDT
+        assert_eq!(boundary.start_of(&New_York, day), utc(2024, 3, 10, 7, 0));
+        assert_eq!(boundary.start_of(&est(), day), utc(2024, 3, 10, 7, 0));
+
+        assert_eq!(day_length(DayBoundary::default(), &New_York, day), 23 * 3600);
+        assert_eq!(day_length(DayBoundary::default(), &est(), day), 24 * 3600);
+
+        // The hour before the jump still belongs to the 9th
+        assert_eq!(boundary.date_of(&New_York, utc(2024, 3, 10, 6, 30)), date(2024, 3, 9));
+        assert_eq!(boundary.date_of(&New_York, utc(2024, 3, 10, 7, 0)), day);
+    }
+
+    #[test]
+    fn fall_back_repeated_hour() {
+        let boundary = DayBoundary { start_hour: 1 };
+        let day = date(2024, 11, 3);
+
+        // 01:00 happens twice in New York; the day starts at the first
+        assert_eq!(boundary.start_of(&New_York, day), utc(2024, 11, 3, 5, 0));
+        assert_eq!(boundary.start_of(&est(), day), utc(2024, 11, 3, 6, 0));
+
+        assert_eq!(day_length(DayBoundary::default(), &New_York, day), 25 * 3600);
+        assert_eq!(day_length(DayBoundary::default(), &est(), day), 24 * 3600);
+
+        // Both passes through 01:30 belong to the 3rd
+        assert_eq!(boundary.date_of(&New_York, utc(2024, 11, 3, 5, 30)), day);
+        assert_eq!(boundary.date_of(&New_York, utc(2024, 11, 3, 6, 30)), day);
+    }
+
+    #[test]
+    fn split_across_midnight() {
+        let boundary = DayBoundary::default();
+
+        // 23:30 to 00:30 EST, an ordinary night in both zones
+        let (start, end) = (utc(2024, 1, 16, 4, 30), utc(2024, 1, 16, 5, 30));
+        let midnight = utc(2024, 1, 16, 5, 0);
+        let expected = vec![(date(2024, 1, 15), start, midnight), (date(2024, 1, 16), midnight, end)];
+        assert_eq!(boundary.split(&New_York, start, end), expected);
+        assert_eq!(boundary.split(&est(), start, end), expected);
+
+        // 23:00 EDT on the 2nd to 03:00 EST on the 3rd: midnight is still
+        // EDT in New York, an hour earlier than in the fixed zone
+        let (start, end) = (utc(2024, 11, 3, 3, 0), utc(2024, 11, 3, 8, 0));
+        assert_eq!(
+            boundary.split(&New_York, start, end),
+            vec![
+                (date(2024, 11, 2), start, utc(2024, 11, 3, 4, 0)),
+                (date(2024, 11, 3), utc(2024, 11, 3, 4, 0), end),
+            ]
+        );
+        assert_eq!(
+            boundary.split(&est(), start, end),
+            vec![
+                (date(2024, 11, 2), start, utc(2024, 11, 3, 5, 0)),
+                (date(2024, 11, 3), utc(2024, 11, 3, 5, 0), end),
+            ]
+        );
+
+        // A session within one day stays whole
+        assert_eq!(boundary.split(&est(), start, start + 60), vec![(date(2024, 11, 2), start, start + 60)]);
+    }
+
+    #[test]
+    fn start_hour_4() {
+        let boundary = DayBoundary { start_hour: 4 };
+
+        // 03:59 EST still counts toward the evening before
+        assert_eq!(boundary.date_of(&est(), utc(2024, 1, 16, 8, 59)), date(2024, 1, 15));
+        assert_eq!(boundary.date_of(&est(), utc(2024, 1, 16, 9, 0)), date(2024, 1, 16));
+        assert_eq!(boundary.date_of(&New_York, utc(2024, 1, 16, 8, 59)), date(2024, 1, 15));
+        assert_eq!(boundary.date_of(&New_York, utc(2024, 1, 16, 9, 0)), date(2024, 1, 16));
+
+        // 03:00 to 05:00 local is cut at 04:00
+        let (start, end) = (utc(2024, 1, 16, 8, 0), utc(2024, 1, 16, 10, 0));
+        let expected = vec![
+            (date(2024, 1, 15), start, utc(2024, 1, 16, 9, 0)),
+            (date(2024, 1, 16), utc(2024, 1, 16, 9, 0), end),
+        ];
+        assert_eq!(boundary.split(&New_York, start, end), expected);
+        assert_eq!(boundary.split(&est(), start, end), expected);
+
+        // On the DST days 04:00 is after the change in New York
+        assert_eq!(boundary.start_of(&New_York, date(2024, 3, 10)), utc(2024, 3, 10, 8, 0));
+        assert_eq!(boundary.start_of(&est(), date(2024, 3, 10)), utc(2024, 3, 10, 9, 0));
+        assert_eq!(boundary.start_of(&New_York, date(2024, 11, 3)), utc(2024, 11, 3, 9, 0));
+        assert_eq!(boundary.start_of(&est(), date(2024, 11, 3)), utc(2024, 11, 3, 9, 0));
+        // so the days that contain the change are the ones before
+        assert_eq!(day_length(boundary, &New_York, date(2024, 3, 9)), 23 * 3600);
+        assert_eq!(day_length(boundary, &New_York, date(2024, 3, 10)), 24 * 3600);
+        assert_eq!(day_length(boundary, &New_York, date(2024, 11, 2)), 25 * 3600);
+        assert_eq!(day_length(boundary, &est(), date(2024, 11, 2)), 24 * 3600);
+    }
+}
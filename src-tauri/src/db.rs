@@ -1,8 +1,13 @@
-use rusqlite::{params, Connection, Result};
+use chrono::{Duration, Local, NaiveDate};
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension, Result};
 use serde::{Deserialize, Serialize};
-use std::fs::{self, File};
-use std::path::PathBuf;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crate::days::DayBoundary;
+use crate::error::DeskflowError;
+use crate::migrations;
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct AppSession {
@@ -11,96 +16,135 @@ pub struct AppSession {
     pub category: String,
     pub start_time: u64,
     pub end_time: u64,
+    /// Whether the window had focus, as opposed to merely being visible.
+    #[serde(default)]
+    pub is_focused: bool,
+    /// The categorization rule that produced `category`, if any matched.
+    #[serde(default)]
+    pub rule_id: Option<String>,
 }
 
+/// Seconds spent in a category, split by whether the window had focus.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy)]
+pub struct CategoryTime {
+    pub focused: u64,
+    pub background: u64,
+}
 
-// Go to the following path to find the database file:
-// C:\Users\<YourUser>\AppData\Roaming\deskflow\usage_data.db
 
+// --------------- Shared connection --------------- //
 
-fn get_db_path() -> PathBuf {
-    if cfg!(target_os = "windows") {
-        let mut path = PathBuf::from(std::env::var("APPDATA").unwrap());
-        path.push("deskflow/usage_data.db");
-        path
-    } else {
-        let mut path = PathBuf::from(std::env::var("HOME").unwrap());
-        path.push(".deskflow/usage_data.db");
-        path
-    }
+/// The one connection to usage_data.db, shared by the tracker and every
+/// command through Tauri state. SQLite serialises writers anyway, so a single
+/// connection behind a mutex is all the pooling we need.
+#[derive(Clone)]
+pub struct Db {
+    conn: Arc<Mutex<Connection>>,
+    path: PathBuf,
 }
 
-pub fn init_db() -> Result<()> {
-    let db_path = get_db_path();
-    if let Some(parent) = db_path.parent() {
-        fs::create_dir_all(parent).unwrap();
+impl Db {
+    /// Open (creating if needed) and migrate the database at `db_path`. Its
+    /// directory has to exist.
+    pub fn open(db_path: &Path) -> std::result::Result<Self, DeskflowError> {
+        Self::open_and_migrate(db_path).map_err(|e| DeskflowError::from(e).context(db_path.display()))
     }
 
-    let conn = Connection::open(db_path)?;
+    fn open_and_migrate(db_path: &Path) -> Result<Self> {
+        let mut conn = Connection::open(db_path)?;
+        // WAL lets the HUD read while the tracker writes, and NORMAL sync is
+        // durable enough for a usage log while keeping commits cheap
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        conn.pragma_update(None, "synchronous", "NORMAL")?;
+        conn.busy_timeout(std::time::Duration::from_secs(5))?;
+        conn.set_prepared_statement_cache_capacity(32);
 
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS app_usage (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            app_name TEXT NOT NULL,
-            window_title TEXT NOT NULL,
-            category TEXT NOT NULL,
-            start_time INTEGER NOT NULL,
-            end_time INTEGER NOT NULL,
-            date TEXT NOT NULL
-        )",
-        [],
-    )?;
+        migrations::migrate(&mut conn, db_path)?;
 
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS productivity_scores (
-            date TEXT PRIMARY KEY,
-            score REAL NOT NULL
-        )",
-        [],
-    )?;
+        Ok(Self {
+            conn: Arc::new(Mutex::new(conn)),
+            path: db_path.to_path_buf(),
+        })
+    }
 
-    Ok(())
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// A separate read-only connection for long reads such as exports, so
+    /// they don't hold up the tracker. WAL lets it read alongside writes.
+    pub fn open_read_only(&self) -> Result<Connection> {
+        let conn = Connection::open_with_flags(&self.path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+        conn.busy_timeout(std::time::Duration::from_secs(5))?;
+        Ok(conn)
+    }
+
+    pub fn lock(&self) -> MutexGuard<'_, Connection> {
+        // A panic mid-statement doesn't leave the connection unusable
+        self.conn.lock().unwrap_or_else(|e| e.into_inner())
+    }
 }
 
-pub fn save_session_to_db(session: &AppSession) -> Result<()> {
-    let conn = Connection::open(get_db_path())?;
-    let date = chrono::NaiveDateTime::from_timestamp(session.start_time as i64, 0)
-        .date()
-        .to_string();
+/// Insert one tracker tick's worth of sessions in a single transaction.
+/// A session that runs past the start of a day is stored as one row per day.
+pub fn save_sessions(conn: &mut Connection, sessions: &[AppSession]) -> Result<()> {
+    if sessions.is_empty() {
+        return Ok(());
+    }
 
-    conn.execute(
-        "INSERT INTO app_usage (app_name, window_title, category, start_time, end_time, date) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
-        params![
+    let boundary = DayBoundary::load(conn)?;
+    let tx = conn.transaction()?;
+    for session in sessions {
+        insert_session(&tx, &boundary, session)?;
+    }
+    tx.commit()
+}
+
+/// Insert one session, split into one row per day it touches.
+pub fn insert_session(conn: &Connection, boundary: &DayBoundary, session: &AppSession) -> Result<()> {
+    let mut stmt = conn.prepare_cached(
+        "INSERT INTO app_usage (app_name, window_title, category, start_time, end_time, date, is_focused, rule_id) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+    )?;
+
+    for (date, start_time, end_time) in boundary.split(&Local, session.start_time, session.end_time) {
+        stmt.execute(params![
             session.app_name,
             session.window_title,
             session.category,
-            session.start_time,
-            session.end_time,
-            date
-        ],
-    )?;
+            start_time,
+            end_time,
+            date.to_string(),
+            session.is_focused,
+            session.rule_id
+        ])?;
+    }
     Ok(())
 }
 
-pub fn get_category_summary_today() -> Result<std::collections::HashMap<String, u64>> {
-    let conn = Connection::open(get_db_path())?;
-    let today = chrono::Local::now().format("%Y-%m-%d").to_string();
+pub fn get_category_summary_today(conn: &Connection) -> Result<std::collections::HashMap<String, CategoryTime>> {
+    let today = DayBoundary::load(conn)?.today().to_string();
 
-    let mut stmt = conn.prepare(
-        "SELECT category, SUM(end_time - start_time) as total FROM app_usage WHERE date = ?1 GROUP BY category",
+    let mut stmt = conn.prepare_cached(
+        "SELECT category,
+                SUM(CASE WHEN is_focused THEN end_time - start_time ELSE 0 END) as focused,
+                SUM(CASE WHEN is_focused THEN 0 ELSE end_time - start_time END) as background
+         FROM app_usage WHERE date = ?1 GROUP BY category",
     )?;
 
     let mut map = std::collections::HashMap::new();
 
     let rows = stmt.query_map([today], |row| {
         let category: String = row.get(0)?;
-        let total: u64 = row.get(1)?;
-        Ok((category, total))
+        let time = CategoryTime {
+            focused: row.get(1)?,
+            background: row.get(2)?,
+        };
+        Ok((category, time))
     })?;
 
     for row in rows {
-        let (category, total) = row?;
-        map.insert(category, total);
+        let (category, time) = row?;
+        map.insert(category, time);
     }
 
     Ok(map)
@@ -109,101 +153,193 @@ pub fn get_category_summary_today() -> Result<std::collections::HashMap<String,
 
 // --------------- Calculate and store yesterday's productivity score --------------- //
 
-pub fn store_productivity_score(date: &str, score: f64) -> Result<()> {
-    let conn = Connection::open(get_db_path())?;
-    
+pub fn store_productivity_score(
+    conn: &Connection,
+    date: &str,
+    score: f64,
+    model: &str,
+    model_version: u32,
+) -> Result<()> {
     conn.execute(
-        "INSERT OR REPLACE INTO productivity_scores (date, score) VALUES (?1, ?2)",
-        params![date, score],
+        "INSERT OR REPLACE INTO productivity_scores (date, score, model, model_version)
+         VALUES (?1, ?2, ?3, ?4)",
+        params![date, score, model, model_version],
     )?;
     
     Ok(())
 }
 
 
-use std::io::{Read, Write};
-
-fn get_last_run_file_path() -> PathBuf {
-    let mut path = get_db_path();
-    path.pop(); // remove usage_data.db
-    path.push("last_run.txt");
-    path
-}
+// --------------- Key/value state kept alongside the data --------------- //
 
-pub fn get_last_run_date() -> Option<String> {
-    let path = get_last_run_file_path();
-    if path.exists() {
-        let mut contents = String::new();
-        if File::open(path).unwrap().read_to_string(&mut contents).is_ok() {
-            return Some(contents.trim().to_string());
-        }
-    }
-    None
+pub fn get_state(conn: &Connection, key: &str) -> Result<Option<String>> {
+    conn.query_row("SELECT value FROM app_state WHERE key = ?1", [key], |row| row.get(0))
+        .optional()
 }
 
-pub fn set_last_run_date(date: &str) {
-    let path = get_last_run_file_path();
-    if let Ok(mut file) = File::create(path) {
-        let _ = file.write_all(date.as_bytes());
-    }
+pub fn set_state(conn: &Connection, key: &str, value: &str) -> Result<()> {
+    conn.execute(
+        "INSERT OR REPLACE INTO app_state (key, value) VALUES (?1, ?2)",
+        params![key, value],
+    )?;
+    Ok(())
 }
 
-pub fn clear_app_usage_if_new_day() -> Result<()> {
-    let today = chrono::Local::now().format("%Y-%m-%d").to_string();
-    let last_run = get_last_run_date();
+const LAST_RUN_KEY: &str = "last_run_date";
 
-    if last_run.as_deref() != Some(&today) {
+/// Older versions remembered the last run in a `last_run.txt` next to the
+/// database. Move it into `app_state` and drop the file.
+pub fn migrate_last_run_file(conn: &Connection, data_dir: &Path) -> Result<()> {
+    let path = data_dir.join("last_run.txt");
 
+    if let Ok(contents) = fs::read_to_string(&path) {
+        let date = contents.trim();
+        if !date.is_empty() && get_state(conn, LAST_RUN_KEY)?.is_none() {
+            set_state(conn, LAST_RUN_KEY, date)?;
+        }
+        let _ = fs::remove_file(&path);
+    }
 
+    Ok(())
+}
 
-        // Clear the table
-        let conn = Connection::open(get_db_path())?;
-        conn.execute("DELETE FROM app_usage", [])?;
 
-        // Update last run
-        set_last_run_date(&today);
-    }
+// --------------- History retention --------------- //
 
-    Ok(())
+/// How long raw `app_usage` rows are kept. Older days are rolled up into one
+/// `daily_app_usage` row per app and category, which is kept for good.
+/// The daily jobs build it from the `history` settings on every pass, see
+/// `Settings::retention_policy`; the defaults here are only the fallback.
+#[derive(Clone, Debug)]
+pub struct RetentionPolicy {
+    pub raw_days: u32,
+    /// Once a day is over, merge its back-to-back rows of the same window.
+    pub compact: bool,
 }
 
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        Self {
+            raw_days: 90,
+            compact: true,
+        }
+    }
+}
 
-// --------------- Get productivity score for last five days --------------- //
+/// Roll up and delete raw rows older than `policy.raw_days` before `today`.
+/// Returns the number of raw rows removed.
+pub fn apply_retention(conn: &mut Connection, policy: &RetentionPolicy, today: NaiveDate) -> Result<usize> {
+    let cutoff = (today - Duration::days(i64::from(policy.raw_days))).to_string();
+
+    let tx = conn.transaction()?;
+    tx.execute(
+        "INSERT INTO daily_app_usage (date, app_name, category, focused_seconds, background_seconds)
+         SELECT date, app_name, category,
+                SUM(CASE WHEN is_focused THEN end_time - start_time ELSE 0 END),
+                SUM(CASE WHEN is_focused THEN 0 ELSE end_time - start_time END)
+         FROM app_usage WHERE date < ?1
+         GROUP BY date, app_name, category
+         ON CONFLICT (date, app_name, category) DO UPDATE SET
+            focused_seconds = focused_seconds + excluded.focused_seconds,
+            background_seconds = background_seconds + excluded.background_seconds",
+        [&cutoff],
+    )?;
+    let removed = tx.execute("DELETE FROM app_usage WHERE date < ?1", [&cutoff])?;
+    tx.commit()?;
 
-#[derive(Serialize)]
-pub struct ScoreRecord {
-    pub date: String,
-    pub score: f64,
+    Ok(removed)
 }
 
-#[tauri::command]
-pub fn get_last_five_scores() -> Result<Vec<ScoreRecord>, String> {
-    use rusqlite::Connection;
+/// Run the retention policy once per day, on the first start of that day.
+pub fn apply_retention_if_new_day(conn: &mut Connection, policy: &RetentionPolicy) -> Result<()> {
+    let today = DayBoundary::load(conn)?.today();
+    let today_str = today.to_string();
 
-    let conn = Connection::open(get_db_path()).map_err(|e| e.to_string())?;
+    if get_state(conn, LAST_RUN_KEY)?.as_deref() != Some(today_str.as_str()) {
+        apply_retention(conn, policy, today)?;
+        set_state(conn, LAST_RUN_KEY, &today_str)?;
+    }
+
+    Ok(())
+}
 
-    let mut stmt = conn
-        .prepare("SELECT date, score FROM productivity_scores ORDER BY date DESC LIMIT 5")
-        .map_err(|e| e.to_string())?;
 
-    let rows = stmt
-        .query_map([], |row| {
-            Ok(ScoreRecord {
-                date: row.get(0)?,
-                score: row.get(1)?,
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration as StdDuration, Instant};
+    use tempfile::TempDir;
+
+    fn sessions(count: usize, tick: u64) -> Vec<AppSession> {
+        let start = 1_700_000_000 + tick * 5;
+        (0..count)
+            .map(|i| AppSession {
+                app_name: format!("app{}", i % 8),
+                window_title: format!("window {}", i),
+                category: "Work".to_string(),
+                start_time: start,
+                end_time: start + 5,
+                is_focused: i == 0,
+                rule_id: Some("vscode".to_string()),
             })
-        })
-        .map_err(|e| e.to_string())?;
+            .collect()
+    }
 
-    let mut records = Vec::new();
-    for row in rows {
-        if let Ok(record) = row {
-            records.push(record);
-        }
+    /// What every session cost before the shared connection: its own
+    /// `Connection::open` and an autocommitted insert, on a rollback journal.
+    fn save_session_opening_connection(path: &Path, session: &AppSession) -> Result<()> {
+        let conn = Connection::open(path)?;
+        conn.execute(
+            "INSERT INTO app_usage (app_name, window_title, category, start_time, end_time, date, is_focused) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                session.app_name,
+                session.window_title,
+                session.category,
+                session.start_time,
+                session.end_time,
+                "2023-11-14",
+                session.is_focused
+            ],
+        )?;
+        Ok(())
     }
 
-    // Optional: Sort by ascending date (oldest to newest)
-    records.sort_by(|a, b| a.date.cmp(&b.date));
+    fn per_tick(elapsed: StdDuration, ticks: u64) -> StdDuration {
+        elapsed / ticks as u32
+    }
 
-    Ok(records)
+    /// Write cost per tracker tick. Not a correctness test; run with
+    /// `cargo test --release bench_save_sessions -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn bench_save_sessions() {
+        const TICKS: u64 = 200;
+
+        for count in [10, 50] {
+            let dir = TempDir::new().unwrap();
+
+            let db = Db::open(&dir.path().join("usage_data.db")).unwrap();
+            let started = Instant::now();
+            for tick in 0..TICKS {
+                save_sessions(&mut db.lock(), &sessions(count, tick)).unwrap();
+            }
+            let shared = per_tick(started.elapsed(), TICKS);
+
+            let old_path = dir.path().join("old.db");
+            let mut conn = Connection::open(&old_path).unwrap();
+            migrations::migrate(&mut conn, &old_path).unwrap();
+            drop(conn);
+            let started = Instant::now();
+            for tick in 0..TICKS {
+                for session in sessions(count, tick) {
+                    save_session_opening_connection(&old_path, &session).unwrap();
+                }
+            }
+            let opening = per_tick(started.elapsed(), TICKS);
+
+            println!(
+                "{count} sessions per tick: {shared:?} shared WAL connection, {opening:?} connection per session"
+            );
+        }
+    }
 }
@@ -0,0 +1,232 @@
+// --------------------------------------------------------------------------
+// The error type of the backend. Commands return it as is; the frontend
+// receives `{ "kind": "sqlite", "message": "database is locked" }` and can
+// tell a broken database from a typo in a rule without parsing strings.
+// --------------------------------------------------------------------------
+
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+
+#[derive(Debug)]
+pub enum DeskflowError {
+    /// Reading or writing a file failed.
+    Io(io::Error),
+    /// The database refused a statement.
+    Sqlite(rusqlite::Error),
+    /// Invalid settings, rules or input, usually from the frontend.
+    Config(String),
+    /// The operating system could not tell us something we need.
+    Platform(String),
+}
+
+impl DeskflowError {
+    /// An I/O error that names the file it happened on.
+    pub fn io_at(path: &Path, e: io::Error) -> Self {
+        Self::Io(e).context(path.display())
+    }
+
+    /// Prefix the message with `what`, e.g. the line of a file it came
+    /// from. SQLite errors other than plain failures keep their message.
+    pub fn context(self, what: impl fmt::Display) -> Self {
+        match self {
+            Self::Io(e) => Self::Io(io::Error::new(e.kind(), format!("{}: {}", what, e))),
+            Self::Sqlite(rusqlite::Error::SqliteFailure(code, message)) => {
+                let message = message.unwrap_or_else(|| code.to_string());
+                Self::Sqlite(rusqlite::Error::SqliteFailure(code, Some(format!("{}: {}", what, message))))
+            }
+            Self::Sqlite(e) => Self::Sqlite(e),
+            Self::Config(message) => Self::Config(format!("{}: {}", what, message)),
+            Self::Platform(message) => Self::Platform(format!("{}: {}", what, message)),
+        }
+    }
+
+    pub fn config(message: impl Into<String>) -> Self {
+        Self::Config(message.into())
+    }
+
+    pub fn platform(message: impl Into<String>) -> Self {
+        Self::Platform(message.into())
+    }
+
+    /// The `kind` the frontend sees.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Io(_) => "io",
+            Self::Sqlite(_) => "sqlite",
+            Self::Config(_) => "config",
+            Self::Platform(_) => "platform",
+        }
+    }
+}
+
+impl fmt::Display for DeskflowError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => e.fmt(f),
+            Self::Sqlite(e) => e.fmt(f),
+            Self::Config(message) | Self::Platform(message) => f.write_str(message),
+        }
+    }
+}
+
+impl std::error::Error for DeskflowError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            Self::Sqlite(e) => Some(e),
+            Self::Config(_) | Self::Platform(_) => None,
+        }
+    }
+}
+
+impl Serialize for DeskflowError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("DeskflowError", 2)?;
+        state.serialize_field("kind", self.kind())?;
+        state.serialize_field("message", &self.to_string())?;
+        state.end()
+    }
+}
+
+impl From<io::Error> for DeskflowError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<rusqlite::Error> for DeskflowError {
+    fn from(e: rusqlite::Error) -> Self {
+        Self::Sqlite(e)
+    }
+}
+
+impl From<csv::Error> for DeskflowError {
+    fn from(e: csv::Error) -> Self {
+        Self::Io(e.into())
+    }
+}
+
+impl From<serde_json::Error> for DeskflowError {
+    fn from(e: serde_json::Error) -> Self {
+        if e.is_io() {
+            Self::Io(e.into())
+        } else {
+            Self::Config(e.to_string())
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    fn wire(err: &DeskflowError) -> Value {
+        serde_json::to_value(err).unwrap()
+    }
+
+    fn sqlite_failure(code: std::os::raw::c_int, message: Option<&str>) -> DeskflowError {
+        rusqlite::Error::SqliteFailure(rusqlite::ffi::Error::new(code), message.map(str::to_string)).into()
+    }
+
+    #[test]
+    fn every_kind_serializes_as_kind_and_message() {
+        let cases = [
+            (
+                DeskflowError::from(io::Error::new(io::ErrorKind::NotFound, "no such file")),
+                "io",
+                "no such file",
+            ),
+            (
+                sqlite_failure(rusqlite::ffi::SQLITE_BUSY, Some("database is locked")),
+                "sqlite",
+                "database is locked",
+            ),
+            (
+                DeskflowError::config("poll interval too short"),
+                "config",
+                "poll interval too short",
+            ),
+            (
+                DeskflowError::platform("HOME is not set"),
+                "platform",
+                "HOME is not set",
+            ),
+        ];
+
+        for (err, kind, message) in cases {
+            assert_eq!(wire(&err), json!({ "kind": kind, "message": message }));
+        }
+    }
+
+    #[test]
+    fn context_prefixes_the_message_and_keeps_the_kind() {
+        let io = DeskflowError::io_at(
+            Path::new("/tmp/rules.json"),
+            io::Error::new(io::ErrorKind::NotFound, "gone"),
+        );
+        assert_eq!(
+            wire(&io),
+            json!({ "kind": "io", "message": "/tmp/rules.json: gone" })
+        );
+        let DeskflowError::Io(inner) = &io else {
+            panic!("not an I/O error: {:?}", io);
+        };
+        assert_eq!(inner.kind(), io::ErrorKind::NotFound);
+
+        let sqlite =
+            sqlite_failure(rusqlite::ffi::SQLITE_BUSY, Some("database is locked")).context("usage_data.db");
+        assert_eq!(
+            wire(&sqlite),
+            json!({ "kind": "sqlite", "message": "usage_data.db: database is locked" })
+        );
+
+        // A failure without a message gets the code's description
+        let bare = sqlite_failure(rusqlite::ffi::SQLITE_BUSY, None).context("usage_data.db");
+        let message = wire(&bare)["message"].as_str().unwrap().to_string();
+        assert!(message.starts_with("usage_data.db: "), "{}", message);
+        assert!(message.len() > "usage_data.db: ".len(), "{}", message);
+
+        let config = DeskflowError::config("missing header").context("line 1");
+        assert_eq!(
+            wire(&config),
+            json!({ "kind": "config", "message": "line 1: missing header" })
+        );
+
+        let platform = DeskflowError::platform("no monitor").context("HUD");
+        assert_eq!(
+            wire(&platform),
+            json!({ "kind": "platform", "message": "HUD: no monitor" })
+        );
+    }
+
+    #[test]
+    fn other_sqlite_errors_keep_their_message() {
+        let err = DeskflowError::from(rusqlite::Error::QueryReturnedNoRows).context("scores");
+        assert_eq!(
+            wire(&err),
+            json!({ "kind": "sqlite", "message": "Query returned no rows" })
+        );
+    }
+
+    #[test]
+    fn json_errors_are_config_errors_unless_they_come_from_io() {
+        let syntax = serde_json::from_str::<Value>("{").unwrap_err();
+        assert_eq!(DeskflowError::from(syntax).kind(), "config");
+
+        let io = serde_json::from_reader::<_, Value>(FailingReader).unwrap_err();
+        assert_eq!(DeskflowError::from(io).kind(), "io");
+    }
+
+    struct FailingReader;
+
+    impl io::Read for FailingReader {
+        fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+            Err(io::Error::new(io::ErrorKind::BrokenPipe, "pipe closed"))
+        }
+    }
+}
@@ -0,0 +1,226 @@
+// --------------------------------------------------------------------------
+// CSV export. Rows are written to the file as they are read, over a
+// read-only connection of their own, so exporting years of history neither
+// builds it all in memory nor holds up the tracker.
+// --------------------------------------------------------------------------
+
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+use chrono::{Local, NaiveDate, TimeZone};
+use rusqlite::Connection;
+use tauri::State;
+
+use crate::db::Db;
+use crate::error::DeskflowError;
+use crate::reports::parse_date_range;
+
+fn iso_time(timestamp: u64) -> String {
+    Local
+        .timestamp_opt(timestamp as i64, 0)
+        .single()
+        .map(|t| t.to_rfc3339())
+        .unwrap_or_default()
+}
+
+fn create_writer(path: &Path) -> Result<csv::Writer<BufWriter<File>>, DeskflowError> {
+    let file = File::create(path).map_err(|e| DeskflowError::io_at(path, e))?;
+    Ok(csv::Writer::from_writer(BufWriter::new(file)))
+}
+
+/// Write every `app_usage` row between `from` and `to` to `path`. Returns
+/// the number of rows written.
+pub fn export_sessions(conn: &Connection, from: NaiveDate, to: NaiveDate, path: &Path) -> Result<u64, DeskflowError> {
+    let mut writer = create_writer(path)?;
+    writer.write_record([
+        "date", "app_name", "window_title", "category", "rule_id", "focused", "start", "end",
+        "duration_seconds",
+    ])?;
+
+    let mut stmt = conn.prepare(
+        "SELECT date, app_name, window_title, category, rule_id, is_focused, start_time, end_time
+         FROM app_usage WHERE date BETWEEN ?1 AND ?2
+         ORDER BY start_time, id",
+    )?;
+    let mut rows = stmt.query((from.to_string(), to.to_string()))?;
+
+    let mut written = 0;
+    while let Some(row) = rows.next()? {
+        let (date, app_name, title, category, rule_id, focused, start, end) = (
+            row.get::<_, String>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, String>(2)?,
+            row.get::<_, String>(3)?,
+            row.get::<_, Option<String>>(4)?,
+            row.get::<_, bool>(5)?,
+            row.get::<_, u64>(6)?,
+            row.get::<_, u64>(7)?,
+        );
+
+        writer.write_record([
+            date,
+            app_name,
+            title,
+            category,
+            rule_id.unwrap_or_default(),
+            focused.to_string(),
+            iso_time(start),
+            iso_time(end),
+            end.saturating_sub(start).to_string(),
+        ])?;
+        written += 1;
+    }
+
+    writer.flush()?;
+    Ok(written)
+}
+
+/// Write focused and background seconds per day and category between
+/// `from` and `to` to `path`, including days already rolled up. Returns the
+/// number of rows written.
+pub fn export_daily_summary(conn: &Connection, from: NaiveDate, to: NaiveDate, path: &Path) -> Result<u64, DeskflowError> {
+    let mut writer = create_writer(path)?;
+    writer.write_record(["date", "category", "focused_seconds", "background_seconds"])?;
+
+    let mut stmt = conn.prepare(
+        "SELECT date, category, SUM(focused), SUM(background) FROM (
+            SELECT date, category,
+                   CASE WHEN is_focused THEN end_time - start_time ELSE 0 END AS focused,
+                   CASE WHEN is_focused THEN 0 ELSE end_time - start_time END AS background
+            FROM app_usage WHERE date BETWEEN ?1 AND ?2
+            UNION ALL
+            SELECT date, category, focused_seconds, background_seconds
+            FROM daily_app_usage WHERE date BETWEEN ?1 AND ?2
+         )
+         GROUP BY date, category
+         ORDER BY date, category",
+    )?;
+    let mut rows = stmt.query((from.to_string(), to.to_string()))?;
+
+    let mut written = 0;
+    while let Some(row) = rows.next()? {
+        let (date, category, focused, background) = (
+            row.get::<_, String>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, u64>(2)?,
+            row.get::<_, u64>(3)?,
+        );
+
+        writer.write_record([date, category, focused.to_string(), background.to_string()])?;
+        written += 1;
+    }
+
+    writer.flush()?;
+    Ok(written)
+}
+
+
+// --------------- Commands --------------- //
+
+/// Export the sessions between `from` and `to` to the CSV file at `path`.
+#[tauri::command]
+pub fn export_sessions_csv(db: State<'_, Db>, from: String, to: String, path: String) -> Result<u64, DeskflowError> {
+    let (from, to) = parse_date_range(&from, &to)?;
+    let conn = db.open_read_only()?;
+    export_sessions(&conn, from, to, Path::new(&path))
+}
+
+/// Export per-day category totals between `from` and `to` to the CSV file
+/// at `path`.
+#[tauri::command]
+pub fn export_daily_summary_csv(db: State<'_, Db>, from: String, to: String, path: String) -> Result<u64, DeskflowError> {
+    let (from, to) = parse_date_range(&from, &to)?;
+    let conn = db.open_read_only()?;
+    export_daily_summary(&conn, from, to, Path::new(&path))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    const TRICKY_TITLES: [&str; 3] = ["main.rs, lib.rs", "\"quoted\" - Notes", "first line\nsecond line"];
+
+    fn database() -> Connection {
+        let mut conn = Connection::open_in_memory().unwrap();
+        crate::migrations::migrate(&mut conn, Path::new(":memory:")).unwrap();
+        conn
+    }
+
+    fn insert_row(conn: &Connection, title: &str, category: &str, start: u64, end: u64, focused: bool) {
+        conn.execute(
+            "INSERT INTO app_usage (app_name, window_title, category, start_time, end_time, date, is_focused, rule_id)
+             VALUES ('code', ?1, ?2, ?3, ?4, '2024-05-06', ?5, 'vscode')",
+            rusqlite::params![title, category, start, end, focused],
+        )
+        .unwrap();
+    }
+
+    fn may_6() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 5, 6).unwrap()
+    }
+
+    fn read_back(path: &Path) -> (Vec<String>, Vec<Vec<String>>) {
+        let mut reader = csv::Reader::from_path(path).unwrap();
+        let headers = reader.headers().unwrap().iter().map(str::to_string).collect();
+        let rows = reader
+            .records()
+            .map(|record| record.unwrap().iter().map(str::to_string).collect())
+            .collect();
+        (headers, rows)
+    }
+
+    #[test]
+    fn sessions_survive_commas_quotes_and_newlines() {
+        let conn = database();
+        for (i, title) in TRICKY_TITLES.iter().enumerate() {
+            let start = 1_714_989_600 + i as u64 * 60;
+            insert_row(&conn, title, "Work", start, start + 45, true);
+        }
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("sessions.csv");
+
+        assert_eq!(export_sessions(&conn, may_6(), may_6(), &path).unwrap(), 3);
+
+        let (headers, rows) = read_back(&path);
+        assert_eq!(headers[2], "window_title");
+        let titles: Vec<&str> = rows.iter().map(|row| row[2].as_str()).collect();
+        assert_eq!(titles, TRICKY_TITLES);
+        for row in &rows {
+            assert_eq!(row.len(), headers.len());
+            assert_eq!(row[4], "vscode");
+            assert_eq!(row[5], "true");
+            assert_eq!(row[8], "45");
+        }
+    }
+
+    #[test]
+    fn daily_summary_adds_up_raw_and_rolled_up_days() {
+        let conn = database();
+        insert_row(&conn, "main.rs", "Work", 1_714_989_600, 1_714_989_660, true);
+        insert_row(&conn, "Spotify", "Music", 1_714_989_660, 1_714_989_690, false);
+        conn.execute(
+            "INSERT INTO daily_app_usage (date, app_name, category, focused_seconds, background_seconds)
+             VALUES ('2024-05-05', 'code', 'Work', 3600, 10)",
+            [],
+        )
+        .unwrap();
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("summary.csv");
+        let may_5 = NaiveDate::from_ymd_opt(2024, 5, 5).unwrap();
+
+        assert_eq!(export_daily_summary(&conn, may_5, may_6(), &path).unwrap(), 3);
+
+        let (_, rows) = read_back(&path);
+        assert_eq!(
+            rows,
+            [
+                ["2024-05-05", "Work", "3600", "10"],
+                ["2024-05-06", "Music", "0", "30"],
+                ["2024-05-06", "Work", "60", "0"],
+            ]
+        );
+    }
+}
@@ -0,0 +1,74 @@
+// --------------------------------------------------------------------------
+// Fallback idle detection for systems without XScreenSaver. Input devices
+// raise interrupts, so as long as their counters in /proc/interrupts keep
+// moving somebody is at the machine. This is a heuristic: USB controllers
+// also count traffic from non-input devices.
+// --------------------------------------------------------------------------
+
+use std::fs;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use super::IdleDetector;
+
+/// Interrupt sources that carry keyboard and mouse input.
+const INPUT_SOURCES: &[&str] = &["i8042", "keyboard", "mouse", "xhci_hcd", "ehci_hcd", "ohci_hcd", "uhci_hcd"];
+
+pub struct InterruptIdle {
+    state: Mutex<State>,
+}
+
+struct State {
+    last_count: Option<u64>,
+    last_change: Instant,
+}
+
+impl InterruptIdle {
+    pub fn new() -> Self {
+        Self {
+            state: Mutex::new(State {
+                last_count: None,
+                last_change: Instant::now(),
+            }),
+        }
+    }
+}
+
+impl Default for InterruptIdle {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl IdleDetector for InterruptIdle {
+    fn idle_seconds(&self) -> Option<u64> {
+        let count = input_interrupt_count()?;
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+
+        if state.last_count != Some(count) {
+            state.last_count = Some(count);
+            state.last_change = Instant::now();
+        }
+
+        Some(state.last_change.elapsed().as_secs())
+    }
+}
+
+/// Sum of the per-CPU counters of every input interrupt line.
+fn input_interrupt_count() -> Option<u64> {
+    let contents = fs::read_to_string("/proc/interrupts").ok()?;
+
+    let total = contents
+        .lines()
+        .filter(|line| INPUT_SOURCES.iter().any(|source| line.contains(source)))
+        .flat_map(|line| {
+            // "  1:   9   0   IR-IO-APIC   1-edge   i8042" — the counters are
+            // the numeric fields right after the IRQ label
+            line.split_whitespace()
+                .skip(1)
+                .map_while(|field| field.parse::<u64>().ok())
+        })
+        .sum();
+
+    Some(total)
+}
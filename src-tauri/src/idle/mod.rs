@@ -0,0 +1,50 @@
+// --------------------------------------------------------------------------
+// Idle / AFK detection. A backend only has to say how long ago the user last
+// touched the keyboard or mouse; the tracker decides what counts as idle.
+// --------------------------------------------------------------------------
+
+use std::sync::Arc;
+
+#[cfg(target_os = "linux")]
+pub mod interrupts;
+#[cfg(target_os = "windows")]
+pub mod windows;
+#[cfg(target_os = "linux")]
+pub mod x11;
+
+pub trait IdleDetector: Send + Sync {
+    /// Seconds since the last keyboard or mouse input, or `None` when the
+    /// backend can't tell.
+    fn idle_seconds(&self) -> Option<u64>;
+}
+
+/// Used where no backend exists; the user is never considered idle.
+#[cfg(not(any(target_os = "windows", target_os = "linux")))]
+pub struct NoIdleDetection;
+
+#[cfg(not(any(target_os = "windows", target_os = "linux")))]
+impl IdleDetector for NoIdleDetection {
+    fn idle_seconds(&self) -> Option<u64> {
+        None
+    }
+}
+
+#[cfg(target_os = "windows")]
+pub fn default_detector() -> Arc<dyn IdleDetector> {
+    Arc::new(windows::LastInputIdle)
+}
+
+/// XScreenSaver when the X server has the extension, otherwise fall back to
+/// watching input interrupts (e.g. under Wayland).
+#[cfg(target_os = "linux")]
+pub fn default_detector() -> Arc<dyn IdleDetector> {
+    match x11::ScreenSaverIdle::connect(None) {
+        Some(detector) => Arc::new(detector),
+        None => Arc::new(interrupts::InterruptIdle::new()),
+    }
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "linux")))]
+pub fn default_detector() -> Arc<dyn IdleDetector> {
+    Arc::new(NoIdleDetection)
+}
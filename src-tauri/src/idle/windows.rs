@@ -0,0 +1,31 @@
+// --------------------------------------------------------------------------
+// Idle time from GetLastInputInfo, which Windows updates on every keyboard
+// or mouse event in the current session.
+// --------------------------------------------------------------------------
+
+use windows::Win32::{
+    System::SystemInformation::GetTickCount,
+    UI::Input::KeyboardAndMouse::{GetLastInputInfo, LASTINPUTINFO},
+};
+
+use super::IdleDetector;
+
+pub struct LastInputIdle;
+
+impl IdleDetector for LastInputIdle {
+    fn idle_seconds(&self) -> Option<u64> {
+        let mut info = LASTINPUTINFO {
+            cbSize: std::mem::size_of::<LASTINPUTINFO>() as u32,
+            dwTime: 0,
+        };
+
+        unsafe {
+            if !GetLastInputInfo(&mut info).as_bool() {
+                return None;
+            }
+            // Both are milliseconds since boot and wrap together after ~49 days
+            let idle_ms = GetTickCount().wrapping_sub(info.dwTime);
+            Some(u64::from(idle_ms) / 1000)
+        }
+    }
+}
@@ -0,0 +1,41 @@
+// --------------------------------------------------------------------------
+// Idle time from the MIT-SCREEN-SAVER extension, which the X server keeps
+// up to date with the time since the last input event.
+// --------------------------------------------------------------------------
+
+use x11rb::connection::{Connection, RequestConnection};
+use x11rb::protocol::screensaver::{self, ConnectionExt};
+use x11rb::protocol::xproto::Window;
+use x11rb::rust_connection::RustConnection;
+
+use super::IdleDetector;
+
+pub struct ScreenSaverIdle {
+    conn: RustConnection,
+    root: Window,
+}
+
+impl ScreenSaverIdle {
+    /// Connect to `display` (or `$DISPLAY`); `None` if there is no X server
+    /// or it lacks the screensaver extension.
+    pub fn connect(display: Option<&str>) -> Option<Self> {
+        let (conn, screen_num) = x11rb::connect(display).ok()?;
+        conn.extension_information(screensaver::X11_EXTENSION_NAME)
+            .ok()
+            .flatten()?;
+        let root = conn.setup().roots.get(screen_num)?.root;
+        Some(Self { conn, root })
+    }
+}
+
+impl IdleDetector for ScreenSaverIdle {
+    fn idle_seconds(&self) -> Option<u64> {
+        let info = self
+            .conn
+            .screensaver_query_info(self.root)
+            .ok()?
+            .reply()
+            .ok()?;
+        Some(u64::from(info.ms_since_user_input) / 1000)
+    }
+}
@@ -1,34 +1,54 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-use tauri::{generate_handler, Manager, PhysicalPosition, PhysicalSize, WebviewWindow};
-#[cfg(target_os = "windows")] // gives us .hwnd()
-
+use std::collections::HashMap;
+use std::sync::Arc;
+use tauri::{generate_handler, Manager, PhysicalPosition, PhysicalSize, State, WebviewWindow};
 
+mod analytics;
+mod archive;
+mod backup;
+mod days;
 mod db;
-use db::{AppSession, init_db, save_session_to_db, get_category_summary_today,clear_app_usage_if_new_day,get_last_five_scores};
-
-
-
-
+mod error;
+mod export;
+mod idle;
+mod migrations;
+mod paths;
+mod reports;
+mod rules;
+mod scoring;
+mod settings;
+mod source;
+mod timeline;
+mod tracker;
+
+use db::{apply_retention_if_new_day, get_category_summary_today, CategoryTime, Db};
+use error::DeskflowError;
+use settings::ActiveSettings;
+use source::{WindowInfo, WindowSource};
+use tracker::{SystemClock, Tracker};
 
 #[tauri::command]
-fn init_position(window: tauri::Window) {
+fn init_position(
+    window: tauri::Window,
+    settings: State<'_, ActiveSettings>,
+) -> Result<(), DeskflowError> {
     // place the window bottom‑right on the primary monitor
-    if let Some(monitor) = window.current_monitor().unwrap() {
+    let monitor = window
+        .current_monitor()
+        .map_err(|e| DeskflowError::platform(format!("could not query the monitor: {}", e)))?;
+    if let Some(monitor) = monitor {
         let size = monitor.size();
+        let hud = settings.current().hud;
 
-        let width = 500.0;
-        let height = 400.0;
-        let x_margin = 24.0;
-        let y_margin = 84.0;
+        let x = size.width as f64 - hud.width - hud.margin_x;
+        let y = size.height as f64 - hud.height - hud.margin_y;
 
-        let x = size.width as f64 - width - x_margin;
-        let y = size.height as f64 - height - y_margin;
-
-        let _ = window.set_size(PhysicalSize::new(width, height));
+        let _ = window.set_size(PhysicalSize::new(hud.width, hud.height));
         let _ = window.set_position(PhysicalPosition::new(x, y));
     }
+    Ok(())
 }
 
 // --------------------------------------------------------------------------
@@ -39,7 +59,7 @@ fn init_position(window: tauri::Window) {
 #[cfg(target_os = "windows")]
 mod win {
     use windows::{
-        core::PCSTR,
+        core::{s, PCSTR},
         Win32::{
             Foundation::HWND,
             UI::WindowsAndMessaging::{
@@ -51,20 +71,13 @@ mod win {
 
     pub unsafe fn attach_to_desktop(hwnd: HWND) {
         // locate the desktop’s SHELLDLL_DefView window
-        let progman = FindWindowExA(HWND(0), HWND(0), PCSTR(b"Progman\0".as_ptr()), PCSTR::null());
-        let mut def_view =
-            FindWindowExA(progman, HWND(0), PCSTR(b"SHELLDLL_DefView\0".as_ptr()), PCSTR::null());
+        let progman = FindWindowExA(HWND(0), HWND(0), s!("Progman"), PCSTR::null());
+        let mut def_view = FindWindowExA(progman, HWND(0), s!("SHELLDLL_DefView"), PCSTR::null());
 
         // multi‑monitor setups sometimes stuff it inside a WorkerW window
         if def_view.0 == 0 {
-            let workerw =
-                FindWindowExA(HWND(0), HWND(0), PCSTR(b"WorkerW\0".as_ptr()), PCSTR::null());
-            def_view = FindWindowExA(
-                workerw,
-                HWND(0),
-                PCSTR(b"SHELLDLL_DefView\0".as_ptr()),
-                PCSTR::null(),
-            );
+            let workerw = FindWindowExA(HWND(0), HWND(0), s!("WorkerW"), PCSTR::null());
+            def_view = FindWindowExA(workerw, HWND(0), s!("SHELLDLL_DefView"), PCSTR::null());
         }
 
         // make the desktop the *owner* of our window so it rides that layer
@@ -73,20 +86,12 @@ mod win {
         }
 
         // push to the very bottom so normal windows cover it
-        SetWindowPos(
-            hwnd,
-            HWND_BOTTOM,
-            0,
-            0,
-            0,
-            0,
-            SWP_NOMOVE | SWP_NOSIZE,
-        );
+        let _ = SetWindowPos(hwnd, HWND_BOTTOM, 0, 0, 0, 0, SWP_NOMOVE | SWP_NOSIZE);
     }
 }
 
 fn make_window_desktop_hud(window: &WebviewWindow) {
-     #[cfg(target_os = "windows")]
+    #[cfg(target_os = "windows")]
     if let Ok(raw_hwnd) = window.hwnd() {
         unsafe {
             // convert tauri's HWND to windows::Win32::Foundation::HWND
@@ -95,377 +100,168 @@ fn make_window_desktop_hud(window: &WebviewWindow) {
             win::attach_to_desktop(hwnd);
         }
     }
-}
-
-
-#[cfg(target_os = "windows")]
-use windows::{
-    core::PCWSTR,
-     Win32::{
-        Foundation::{HWND, MAX_PATH, LPARAM},
-        System::{
-            ProcessStatus::K32GetModuleBaseNameW,
-            Threading::{OpenProcess, PROCESS_QUERY_INFORMATION, PROCESS_VM_READ, PROCESS_QUERY_LIMITED_INFORMATION},
-        },
-        UI::WindowsAndMessaging::*,
-    },
-};
-
-#[cfg(target_os = "windows")]
-fn get_active_window_info() -> Option<(String, String)> {
-    unsafe {
-        let hwnd = GetForegroundWindow();
-        if hwnd.0 == 0 {
-            return None;
-        }
 
-        // Get window title
-        let mut title = [0u16; 512];
-        let len = GetWindowTextW(hwnd, &mut title);
-        let title = String::from_utf16_lossy(&title[..len as usize]);
-
-        // Get process ID
-        let mut pid = 0;
-        GetWindowThreadProcessId(hwnd, Some(&mut pid));
-
-        // Open the process
-        let process = OpenProcess(PROCESS_QUERY_INFORMATION | PROCESS_VM_READ, false, pid).ok()?;
-
-        // Get the executable name
-        let mut name = [0u16; MAX_PATH as usize];
-        let len = K32GetModuleBaseNameW(process, None, &mut name) as usize;
-        let process_name = String::from_utf16_lossy(&name[..len]);
-
-        Some((title, process_name))
-    }
+    #[cfg(not(target_os = "windows"))]
+    let _ = window;
 }
 
-
 #[tauri::command]
-fn get_active_app() -> Option<(String, String)> {
-    get_active_window_info()
+fn get_active_app(source: State<'_, Arc<dyn WindowSource>>) -> Option<(String, String)> {
+    source.active_window().map(WindowInfo::into_key)
 }
 
-
-
-
-
-#[cfg(target_os = "windows")]
 #[tauri::command]
-fn get_all_visible_windows() -> Vec<(String, String)> {
-    let mut windows_info: Vec<(String, String)> = Vec::new();
-
-        use windows::Win32::Foundation::BOOL; 
-
-    unsafe extern "system" fn enum_window_proc(hwnd: HWND, lparam: LPARAM) -> BOOL {
-        let windows_info = &mut *(lparam.0 as *mut Vec<(String, String)>);
-
-        // Skip invisible or empty title windows
-        if !IsWindowVisible(hwnd).as_bool() || GetWindowTextLengthW(hwnd) == 0 {
-            return  true.into();
-        }
-
-        // Get window title
-        let mut title = [0u16; 512];
-        let len = GetWindowTextW(hwnd, &mut title);
-        let title = String::from_utf16_lossy(&title[..len as usize]);
-
-        // Skip system/UI windows
-        let skip_titles = ["Program Manager", "Settings", "Windows Input Experience"];
-        if skip_titles.iter().any(|t| title.contains(t)) {
-            return true.into();
-        }
-
-        // Get process ID
-        let mut pid = 0;
-        GetWindowThreadProcessId(hwnd, Some(&mut pid));
-
-        let handle = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION | PROCESS_VM_READ, false, pid);
-        let exe_name = if let Ok(process) = handle {
-            let mut name = [0u16; 260];
-            let len = K32GetModuleBaseNameW(process, None, &mut name);
-
-            if len > 0 {
-                String::from_utf16_lossy(&name[..len as usize])
-            } else {
-                // Fallback for UWP apps
-                use windows::Win32::System::Threading::{OpenProcess, PROCESS_QUERY_LIMITED_INFORMATION, PROCESS_VM_READ, QueryFullProcessImageNameW, PROCESS_NAME_FORMAT};
-                use windows::core::PWSTR;
-
-                let mut full = [0u16; 260];
-                let mut size = full.len() as u32;
-
-                if QueryFullProcessImageNameW(process, PROCESS_NAME_FORMAT(0), PWSTR(full.as_mut_ptr()), &mut size).is_ok() {
-                    String::from_utf16_lossy(&full[..size as usize])
-                        .split('\\')
-                        .last()
-                        .unwrap_or("<unknown>")
-                        .to_string()
-                } else {
-                    "<unknown>".to_string()
-                }
-            }
-        } else {
-            "<access denied>".to_string()
-        };
-
-        // Filter system/UWP background processes
-        let skip_exe = [
-            "SystemSettings.exe", "StartMenuExperienceHost.exe",
-            "ShellExperienceHost.exe", "ApplicationFrameHost.exe",
-            "TextInputHost.exe", "SearchApp.exe"
-        ];
-
-        if skip_exe.iter().any(|p| exe_name.eq_ignore_ascii_case(p)) {
-            return true.into();
-        }
-
-        windows_info.push((title, exe_name));
-        true.into()
- 
-    }
-
-    unsafe {
-        EnumWindows(Some(enum_window_proc), LPARAM(&mut windows_info as *mut _ as isize));
-    }
-
-    windows_info
+fn get_all_visible_windows(source: State<'_, Arc<dyn WindowSource>>) -> Vec<(String, String)> {
+    source
+        .visible_windows()
+        .into_iter()
+        .map(WindowInfo::into_key)
+        .collect()
 }
 
-
-//----- To Track the time period -------//
-
-use serde::{Serialize, Deserialize};
-use std::fs::{OpenOptions};
-use std::io::{BufReader, BufWriter};
-use std::time::{SystemTime, UNIX_EPOCH};
-
-use tokio::sync::Mutex;
-use once_cell::sync::Lazy;
-
-static LAST_APP: Lazy<Mutex<Option<(String, String, u64)>>> = Lazy::new(|| Mutex::new(None));
-
-use std::collections::HashMap;
-
-#[derive(Clone)]
-struct RunningApp {
-    start_time: u64,
-    last_seen: u64,
+#[tauri::command]
+fn get_running_processes(source: State<'_, Arc<dyn WindowSource>>) -> Vec<String> {
+    source.running_processes()
 }
 
-static RUNNING_APPS: Lazy<Mutex<HashMap<(String, String), RunningApp>>> = Lazy::new(|| Mutex::new(HashMap::new()));
-
-
-fn guess_category(title: &str, process: &str) -> String {
-    let lowered = format!("{} {}", title.to_lowercase(), process.to_lowercase());
-
-    if lowered.contains("spotify") {
-        "Music".to_string()
-    } else if lowered.contains("vscode") || lowered.contains("code") {
-        "Work".to_string()
-    } else if lowered.contains("chrome") || lowered.contains("brave") {
-        if lowered.contains("youtube") || lowered.contains("netflix") {
-            "Entertainment".to_string()
-        } else if lowered.contains("docs") || lowered.contains("ChatGPT") || lowered.contains("slack") {
-            "Work".to_string()
-        } else if lowered.contains("github") || lowered.contains("gitlab") || lowered.contains("bitbucket") {
-            "Work".to_string()
-        } else if lowered.contains("research") || lowered.contains("papers") || lowered.contains("arxiv") {
-            "Research".to_string()
-        } else if lowered.contains("education") || lowered.contains("learning") || lowered.contains("courses") {
-            "Education".to_string()
-        }
-        else {
-            "Browsing".to_string()
-        }
-    } else if lowered.contains("game") {
-        "Gaming".to_string()
-    } else if lowered.contains("whatsapp") || lowered.contains("discord") || lowered.contains("teams") || lowered.contains("telegram") {
-        "Chatting".to_string()
-    } else {
-        "Other".to_string()
-    }
+#[tauri::command]
+fn get_paths(paths: State<'_, paths::Paths>) -> paths::Paths {
+    paths.inner().clone()
 }
 
-#[cfg(target_os = "windows")]
 #[tauri::command]
-fn get_running_processes() -> Vec<String> {
-    use windows::{
-        Win32::{
-            System::Diagnostics::ToolHelp::{
-                CreateToolhelp32Snapshot, Process32FirstW, Process32NextW, PROCESSENTRY32W,
-                TH32CS_SNAPPROCESS,
-            },
-            Foundation::HANDLE,
-        },
-    };
-
-    let mut processes = Vec::new();
-
-    unsafe {
-        let snapshot: HANDLE = CreateToolhelp32Snapshot(TH32CS_SNAPPROCESS, 0).unwrap();
+fn get_category_summary(db: State<'_, Db>) -> Result<HashMap<String, CategoryTime>, DeskflowError> {
+    Ok(get_category_summary_today(&db.lock())?)
+}
 
-        if snapshot.0 == -1 {
-            return processes; // failed to get snapshot
+/// Work that has to happen once the date changes: taking the daily backup,
+/// finalizing the productivity score of the day that just ended, rolling up
+/// old history and compacting finished days. Runs right away on startup and
+/// then once a minute, with the policies from the current settings.
+async fn run_daily_jobs(db: Db, settings: ActiveSettings) {
+    loop {
+        let current = settings.current();
+        let (retention, backups) = (current.retention_policy(), current.backup_policy());
+        let today = days::DayBoundary::load(&db.lock())
+            .unwrap_or_default()
+            .today();
+
+        if let Err(e) = backup::backup_if_new_day(db.path(), &backups, today) {
+            eprintln!("Failed to back up the database: {}", e);
         }
 
-        let mut entry = PROCESSENTRY32W {
-            dwSize: std::mem::size_of::<PROCESSENTRY32W>() as u32,
-            ..Default::default()
-        };
-
-        if Process32FirstW(snapshot, &mut entry).is_ok() {
-            loop {
-                let len = entry.szExeFile.iter().position(|&c| c == 0).unwrap_or(entry.szExeFile.len());
-                let name = String::from_utf16_lossy(&entry.szExeFile[..len]);
-
-                // Optional: Filter out system processes
-                let skip = [
-                    "svchost.exe",
-                    "System Idle Process",
-                    "System",
-                    "winlogon.exe",
-                    "csrss.exe",
-                    "smss.exe",
-                    "Registry",
-                    "Idle",
-                ];
-
-                if !skip.iter().any(|&s| s.eq_ignore_ascii_case(&name)) {
-                    processes.push(name.clone());
-                }
-
-                if !Process32NextW(snapshot, &mut entry).is_err() {
-                    break;
-                }
+        // Score finished days from their raw rows, before anything rewrites them
+        if let Err(e) = scoring::finalize_scores(&db.lock(), today) {
+            eprintln!("Failed to finalize productivity scores: {}", e);
+        }
+        if let Err(e) = apply_retention_if_new_day(&mut db.lock(), &retention) {
+            eprintln!("Failed to apply history retention: {}", e);
+        }
+        if retention.compact {
+            let earliest = today - chrono::Duration::days(i64::from(retention.raw_days));
+            if let Err(e) = timeline::compact_finished_days(&mut db.lock(), earliest, today) {
+                eprintln!("Failed to compact sessions: {}", e);
             }
         }
-    }
-
-    processes
-}
-#[tauri::command]
-fn get_category_summary() -> Result<HashMap<String, u64>, String> {
-    get_category_summary_today().map_err(|e| e.to_string())
-}
-
-
 
-
-
-#[tauri::command]
-fn store_score(date: String, score: f64) -> Result<(), String> {
-    db::store_productivity_score(&date, score).map_err(|e| e.to_string())
+        tokio::time::sleep(std::time::Duration::from_secs(60)).await;
+    }
 }
 
-
-
-
-
-
-
-
-
-
-
-
-
-
-
-
-
 fn main() {
     tauri::Builder::default()
         .setup(|app| {
+            // ✅ Find (and if needed create) the data directories
+            let paths = paths::Paths::resolve()?;
+            if let Some(legacy) = paths::legacy_dir() {
+                match paths::migrate_legacy_dir(&paths, &legacy) {
+                    Ok(true) => {
+                        eprintln!("Moved {} to {}", legacy.display(), paths.data_dir.display())
+                    }
+                    Ok(false) => {}
+                    Err(e) => eprintln!("Failed to move {}: {}", legacy.display(), e),
+                }
+            }
+            paths.create_dirs()?;
+            app.manage(paths.clone());
+
+            // ✅ Load the settings, writing the defaults on first run
+            let active_settings = ActiveSettings::load(&paths.settings_file());
+            app.manage(active_settings.clone());
+
             // ✅ Initialize the database
 
-            init_db().expect("Failed to initialize DB");
+            let db = Db::open(&paths.db_path())?;
+            app.manage(db.clone());
+            if let Err(e) = db::migrate_last_run_file(&db.lock(), &paths.data_dir) {
+                eprintln!("Failed to migrate last_run.txt: {}", e);
+            }
 
-            // ✅ Clear app_usage table if the date has changed
-            clear_app_usage_if_new_day().expect("Failed to clear usage table on new day");
+            // ✅ Back up, roll up old history and finalize scores whenever the date changes
+            tauri::async_runtime::spawn(run_daily_jobs(db.clone(), active_settings.clone()));
 
-            
-            
             let window = app
                 .get_webview_window("main")
                 .expect("`main` window not found");
             make_window_desktop_hud(&window);
 
-        let app_handle = app.handle();
-        tauri::async_runtime::spawn(async move {
-        use tokio::time::{sleep, Duration};
-        loop {
-                let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
-
-        let visible_windows = get_all_visible_windows();
-
-        let mut apps = RUNNING_APPS.lock().await;
-
-        // Track seen this tick
-        let mut seen_now = HashMap::new();
-
-        for (title, process) in visible_windows {
-            seen_now.insert((title.clone(), process.clone()), true);
-
-            apps.entry((title.clone(), process.clone()))
-                .and_modify(|entry| {
-                    entry.last_seen = now;
-                })
-                .or_insert(RunningApp {
-                    start_time: now,
-                    last_seen: now,
-                });
-        }
-
-        let flush_interval = 5;
-        // Clean up apps that disappeared
-        apps.retain(|(title, process), app| {
-            let duration = now.saturating_sub(app.start_time);
-            let inactive = app.last_seen < now;
-            let should_flush = duration >= flush_interval;
-
-            if inactive || should_flush {
-        let session = AppSession {
-            app_name: process.clone(),
-            window_title: title.clone(),
-            category: guess_category(title, process),
-            start_time: app.start_time,
-            end_time: if inactive { app.last_seen } else { now },
-        };
-
-        save_session_to_db(&session).unwrap();
-
-        if should_flush && !inactive {
-            // Update the start_time to now for the next flush
-            app.start_time = now;
-            app.last_seen = now;
-            return true; // keep tracking
-        }
-
-        false // stop tracking if app disappeared
-    } else {
-        true // still tracking, nothing to do yet
-    }
-        });
+            // ✅ Start tracking whatever the desktop shows
+            let source = source::default_source();
+            app.manage(source.clone());
 
-        sleep(Duration::from_secs(1)).await;
-             }
-            });
+            // ✅ Load the categorization rules, seeding them on first run
+            if let Err(e) = rules::seed_rules(&mut db.lock(), &paths.rules_file()) {
+                eprintln!("Failed to seed categorization rules: {}", e);
+            }
+            let active_rules = rules::ActiveRules::load(&db.lock());
+            app.manage(active_rules.clone());
+
+            let tracker = Tracker::new(active_settings.current().tracker_config(), active_rules);
+            tauri::async_runtime::spawn(tracker.run(
+                db,
+                active_settings,
+                source,
+                idle::default_detector(),
+                Arc::new(SystemClock),
+            ));
 
             Ok(())
         })
         .invoke_handler(generate_handler![
-                    init_position,
-                    get_active_app,
-                    get_all_visible_windows,
-                    get_running_processes,
-                    get_category_summary,
-                    store_score,
-                     get_last_five_scores,     
-
+            init_position,
+            get_active_app,
+            get_all_visible_windows,
+            get_running_processes,
+            get_category_summary,
+            get_paths,
+            scoring::history::get_scores,
+            reports::get_calendar,
+            reports::get_top_apps,
+            reports::get_top_titles,
+            reports::get_category_breakdown,
+            timeline::get_timeline,
+            analytics::get_hourly_distribution,
+            analytics::get_weekday_distribution,
+            days::get_day_start_hour,
+            days::set_day_start_hour,
+            export::export_sessions_csv,
+            export::export_daily_summary_csv,
+            archive::export_archive,
+            archive::import_archive,
+            backup::get_backups,
+            backup::restore_backup,
+            scoring::get_today_score,
+            scoring::get_scoring_config,
+            scoring::set_scoring_config,
+            rules::list_rules,
+            rules::add_rule,
+            rules::update_rule,
+            rules::delete_rule,
+            rules::test_rule,
+            rules::recategorize_sessions,
+            settings::get_settings,
+            settings::update_settings,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
-
-        
 }
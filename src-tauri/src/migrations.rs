@@ -0,0 +1,438 @@
+// --------------------------------------------------------------------------
+// Schema migrations for usage_data.db. The schema version lives in
+// `PRAGMA user_version`; every migration bumps it by one inside the same
+// transaction as its DDL, so a failed upgrade leaves the previous version
+// intact. Before touching an existing database a copy is written next to it.
+//
+// Never edit a migration that has shipped — append a new one instead.
+// --------------------------------------------------------------------------
+
+use rusqlite::{ffi, Connection, Error, Result, Transaction};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub struct Migration {
+    pub version: u32,
+    pub description: &'static str,
+    pub up: fn(&Transaction) -> Result<()>,
+}
+
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "app_usage and productivity_scores",
+        up: initial_schema,
+    },
+    Migration {
+        version: 2,
+        description: "focused vs. visible time",
+        up: add_is_focused,
+    },
+    Migration {
+        version: 3,
+        description: "daily roll-ups and app_state",
+        up: add_retention_tables,
+    },
+    Migration {
+        version: 4,
+        description: "record the categorization rule",
+        up: add_rule_id,
+    },
+    Migration {
+        version: 5,
+        description: "categorization rules",
+        up: add_rules_table,
+    },
+    Migration {
+        version: 6,
+        description: "record the scoring model",
+        up: add_score_model,
+    },
+    Migration {
+        version: 7,
+        description: "index app_usage by date",
+        up: add_date_indexes,
+    },
+    Migration {
+        version: 8,
+        description: "session dates in local time",
+        up: use_local_dates,
+    },
+];
+
+pub fn latest_version() -> u32 {
+    MIGRATIONS.last().map_or(0, |m| m.version)
+}
+
+pub fn current_version(conn: &Connection) -> Result<u32> {
+    conn.query_row("PRAGMA user_version", [], |row| row.get(0))
+}
+
+/// Bring the database at `db_path` up to the latest schema version.
+/// Returns the version the database was at before.
+pub fn migrate(conn: &mut Connection, db_path: &Path) -> Result<u32> {
+    let from = current_version(conn)?;
+    let latest = latest_version();
+
+    if from > latest {
+        return Err(Error::SqliteFailure(
+            ffi::Error::new(ffi::SQLITE_MISMATCH),
+            Some(format!(
+                "database schema version {from} is newer than this build supports ({latest})"
+            )),
+        ));
+    }
+    if from == latest {
+        return Ok(from);
+    }
+
+    if has_tables(conn)? {
+        backup_before_migration(conn, db_path, from)?;
+    }
+
+    for migration in MIGRATIONS.iter().filter(|m| m.version > from) {
+        eprintln!("Migrating database to v{}: {}", migration.version, migration.description);
+
+        let tx = conn.transaction()?;
+        (migration.up)(&tx)?;
+        tx.pragma_update(None, "user_version", migration.version)?;
+        tx.commit()?;
+    }
+
+    Ok(from)
+}
+
+/// `usage_data.db` → `usage_data.db.v<version>.bak`
+pub fn backup_path(db_path: &Path, version: u32) -> PathBuf {
+    let mut name = db_path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".v{version}.bak"));
+    db_path.with_file_name(name)
+}
+
+fn backup_before_migration(conn: &Connection, db_path: &Path, version: u32) -> Result<()> {
+    let path = backup_path(db_path, version);
+    // VACUUM INTO refuses to overwrite; a leftover from an earlier failed
+    // attempt at the same version is safe to replace
+    let _ = fs::remove_file(&path);
+    conn.execute("VACUUM INTO ?1", [path.to_string_lossy()])?;
+    Ok(())
+}
+
+fn has_tables(conn: &Connection) -> Result<bool> {
+    conn.prepare("SELECT 1 FROM sqlite_master WHERE type = 'table'")?
+        .exists([])
+}
+
+fn has_column(tx: &Transaction, table: &str, column: &str) -> Result<bool> {
+    tx.prepare("SELECT 1 FROM pragma_table_info(?1) WHERE name = ?2")?
+        .exists([table, column])
+}
+
+// ---------------- migrations ---------------- //
+
+fn initial_schema(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS app_usage (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            app_name TEXT NOT NULL,
+            window_title TEXT NOT NULL,
+            category TEXT NOT NULL,
+            start_time INTEGER NOT NULL,
+            end_time INTEGER NOT NULL,
+            date TEXT NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS productivity_scores (
+            date TEXT PRIMARY KEY,
+            score REAL NOT NULL
+        );",
+    )
+}
+
+fn add_is_focused(tx: &Transaction) -> Result<()> {
+    // Databases from before the migration framework may already have it
+    if !has_column(tx, "app_usage", "is_focused")? {
+        tx.execute(
+            "ALTER TABLE app_usage ADD COLUMN is_focused INTEGER NOT NULL DEFAULT 0",
+            [],
+        )?;
+    }
+    Ok(())
+}
+
+fn add_retention_tables(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS daily_app_usage (
+            date TEXT NOT NULL,
+            app_name TEXT NOT NULL,
+            category TEXT NOT NULL,
+            focused_seconds INTEGER NOT NULL,
+            background_seconds INTEGER NOT NULL,
+            PRIMARY KEY (date, app_name, category)
+        );
+        CREATE TABLE IF NOT EXISTS app_state (
+            key TEXT PRIMARY KEY,
+            value TEXT NOT NULL
+        );",
+    )
+}
+
+fn add_rule_id(tx: &Transaction) -> Result<()> {
+    tx.execute("ALTER TABLE app_usage ADD COLUMN rule_id TEXT", [])?;
+    Ok(())
+}
+
+fn add_rules_table(tx: &Transaction) -> Result<()> {
+    // `conditions` holds the rule's condition list as JSON
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS categorization_rules (
+            id TEXT PRIMARY KEY,
+            category TEXT NOT NULL,
+            priority INTEGER NOT NULL DEFAULT 0,
+            conditions TEXT NOT NULL
+        );",
+    )
+}
+
+fn add_score_model(tx: &Transaction) -> Result<()> {
+    // Scores stored before this were computed by the frontend; both stay NULL
+    tx.execute_batch(
+        "ALTER TABLE productivity_scores ADD COLUMN model TEXT;
+        ALTER TABLE productivity_scores ADD COLUMN model_version INTEGER;",
+    )
+}
+
+fn add_date_indexes(tx: &Transaction) -> Result<()> {
+    // daily_app_usage and productivity_scores already lead their primary
+    // keys with `date`
+    tx.execute_batch("CREATE INDEX IF NOT EXISTS idx_app_usage_date ON app_usage (date);")
+}
+
+fn use_local_dates(tx: &Transaction) -> Result<()> {
+    // Dates used to be taken in UTC. Re-derive them in the machine's local
+    // time, with days starting at midnight as they still do by default.
+    // Roll-ups and scores of past days stay as they were.
+    tx.execute(
+        "UPDATE app_usage SET date = date(start_time, 'unixepoch', 'localtime')",
+        [],
+    )?;
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Local, TimeZone};
+    use tempfile::TempDir;
+
+    const START: i64 = 1_700_000_000;
+
+    /// The schema `init_db` created before there were migrations.
+    const V0_SCHEMA: &str = "
+        CREATE TABLE app_usage (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            app_name TEXT NOT NULL,
+            window_title TEXT NOT NULL,
+            category TEXT NOT NULL,
+            start_time INTEGER NOT NULL,
+            end_time INTEGER NOT NULL,
+            date TEXT NOT NULL
+        );
+        CREATE TABLE productivity_scores (
+            date TEXT PRIMARY KEY,
+            score REAL NOT NULL
+        );";
+
+    /// After focus tracking, still without `user_version`.
+    const FOCUS_SCHEMA: &str = "
+        CREATE TABLE app_usage (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            app_name TEXT NOT NULL,
+            window_title TEXT NOT NULL,
+            category TEXT NOT NULL,
+            start_time INTEGER NOT NULL,
+            end_time INTEGER NOT NULL,
+            date TEXT NOT NULL,
+            is_focused INTEGER NOT NULL DEFAULT 0
+        );
+        CREATE TABLE productivity_scores (
+            date TEXT PRIMARY KEY,
+            score REAL NOT NULL
+        );";
+
+    /// After history retention, still without `user_version`.
+    const RETENTION_TABLES: &str = "
+        CREATE TABLE daily_app_usage (
+            date TEXT NOT NULL,
+            app_name TEXT NOT NULL,
+            category TEXT NOT NULL,
+            focused_seconds INTEGER NOT NULL,
+            background_seconds INTEGER NOT NULL,
+            PRIMARY KEY (date, app_name, category)
+        );
+        CREATE TABLE app_state (
+            key TEXT PRIMARY KEY,
+            value TEXT NOT NULL
+        );";
+
+    fn fixture(schema: &str) -> (TempDir, PathBuf, Connection) {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("usage_data.db");
+        let conn = Connection::open(&path).unwrap();
+        conn.execute_batch(schema).unwrap();
+        (dir, path, conn)
+    }
+
+    fn columns(conn: &Connection, table: &str) -> Vec<String> {
+        conn.prepare("SELECT name FROM pragma_table_info(?1)")
+            .unwrap()
+            .query_map([table], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap()
+    }
+
+    fn exists(conn: &Connection, kind: &str, name: &str) -> bool {
+        conn.prepare("SELECT 1 FROM sqlite_master WHERE type = ?1 AND name = ?2")
+            .unwrap()
+            .exists([kind, name])
+            .unwrap()
+    }
+
+    fn local_date(timestamp: i64) -> String {
+        Local.timestamp_opt(timestamp, 0).unwrap().date_naive().to_string()
+    }
+
+    fn assert_latest_schema(conn: &Connection) {
+        assert_eq!(current_version(conn).unwrap(), latest_version());
+
+        let app_usage = columns(conn, "app_usage");
+        for column in ["is_focused", "rule_id"] {
+            assert!(app_usage.iter().any(|c| c == column), "app_usage lacks {column}");
+        }
+        let scores = columns(conn, "productivity_scores");
+        for column in ["model", "model_version"] {
+            assert!(scores.iter().any(|c| c == column), "productivity_scores lacks {column}");
+        }
+        for table in ["daily_app_usage", "app_state", "categorization_rules"] {
+            assert!(exists(conn, "table", table), "{table} is missing");
+        }
+        assert!(exists(conn, "index", "idx_app_usage_date"));
+    }
+
+    /// The backup written before migrating from `version`.
+    fn backup(path: &Path, version: u32) -> Connection {
+        let backup = backup_path(path, version);
+        assert!(backup.exists(), "{} was not written", backup.display());
+        Connection::open(backup).unwrap()
+    }
+
+    #[test]
+    fn upgrades_baseline_v0() {
+        let (_dir, path, mut conn) = fixture(V0_SCHEMA);
+        // Dates used to be UTC; an obviously wrong one shows the re-dating
+        conn.execute_batch(&format!(
+            "INSERT INTO app_usage (app_name, window_title, category, start_time, end_time, date)
+             VALUES ('code', 'main.rs', 'Work', {START}, {}, '1999-01-01');
+             INSERT INTO productivity_scores (date, score) VALUES ('2023-11-14', 72.5);",
+            START + 5
+        ))
+        .unwrap();
+
+        assert_eq!(migrate(&mut conn, &path).unwrap(), 0);
+        assert_latest_schema(&conn);
+
+        let (date, focused, rule_id): (String, bool, Option<String>) = conn
+            .query_row("SELECT date, is_focused, rule_id FROM app_usage", [], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+            })
+            .unwrap();
+        assert_eq!(date, local_date(START));
+        assert!(!focused);
+        assert_eq!(rule_id, None);
+
+        let (score, model): (f64, Option<String>) = conn
+            .query_row("SELECT score, model FROM productivity_scores", [], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap();
+        assert_eq!((score, model), (72.5, None));
+
+        let backup = backup(&path, 0);
+        assert_eq!(current_version(&backup).unwrap(), 0);
+        let date: String = backup.query_row("SELECT date FROM app_usage", [], |row| row.get(0)).unwrap();
+        assert_eq!(date, "1999-01-01");
+    }
+
+    #[test]
+    fn upgrades_focus_tracking_era() {
+        let (_dir, path, mut conn) = fixture(FOCUS_SCHEMA);
+        conn.execute(
+            "INSERT INTO app_usage (app_name, window_title, category, start_time, end_time, date, is_focused)
+             VALUES ('code', 'main.rs', 'Work', ?1, ?2, '1999-01-01', 1)",
+            [START, START + 5],
+        )
+        .unwrap();
+
+        assert_eq!(migrate(&mut conn, &path).unwrap(), 0);
+        assert_latest_schema(&conn);
+        // is_focused was already there and must not be added twice
+        assert_eq!(columns(&conn, "app_usage").iter().filter(|c| *c == "is_focused").count(), 1);
+
+        let (date, focused): (String, bool) = conn
+            .query_row("SELECT date, is_focused FROM app_usage", [], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap();
+        assert_eq!(date, local_date(START));
+        assert!(focused);
+
+        backup(&path, 0);
+    }
+
+    #[test]
+    fn upgrades_retention_era() {
+        let (_dir, path, mut conn) = fixture(&format!("{FOCUS_SCHEMA}{RETENTION_TABLES}"));
+        conn.execute_batch(
+            "INSERT INTO daily_app_usage VALUES ('2023-01-02', 'code', 'Work', 3600, 60);
+             INSERT INTO app_state VALUES ('last_run_date', '2023-11-14');",
+        )
+        .unwrap();
+
+        assert_eq!(migrate(&mut conn, &path).unwrap(), 0);
+        assert_latest_schema(&conn);
+
+        let rollup: (String, u64, u64) = conn
+            .query_row("SELECT date, focused_seconds, background_seconds FROM daily_app_usage", [], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+            })
+            .unwrap();
+        assert_eq!(rollup, ("2023-01-02".to_string(), 3600, 60));
+        let last_run: String = conn
+            .query_row("SELECT value FROM app_state WHERE key = 'last_run_date'", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(last_run, "2023-11-14");
+
+        let backup = backup(&path, 0);
+        assert!(exists(&backup, "table", "daily_app_usage"));
+    }
+
+    #[test]
+    fn new_database_gets_no_backup() {
+        let (_dir, path, mut conn) = fixture("");
+
+        assert_eq!(migrate(&mut conn, &path).unwrap(), 0);
+        assert_latest_schema(&conn);
+        assert!(!backup_path(&path, 0).exists());
+
+        // Running again is a no-op
+        assert_eq!(migrate(&mut conn, &path).unwrap(), latest_version());
+    }
+
+    #[test]
+    fn rejects_newer_version() {
+        let (_dir, path, mut conn) = fixture(V0_SCHEMA);
+        conn.pragma_update(None, "user_version", latest_version() + 1).unwrap();
+
+        let err = migrate(&mut conn, &path).unwrap_err();
+        assert!(err.to_string().contains("newer than this build supports"), "{err}");
+        assert_eq!(current_version(&conn).unwrap(), latest_version() + 1);
+        assert!(!backup_path(&path, latest_version() + 1).exists());
+    }
+}
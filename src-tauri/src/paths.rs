@@ -0,0 +1,423 @@
+// --------------------------------------------------------------------------
+// Where DeskFlow keeps its files, per platform:
+//
+//   Windows  data, config  %APPDATA%\deskflow
+//            cache         %LOCALAPPDATA%\deskflow
+//   Linux    data          $XDG_DATA_HOME/deskflow   (~/.local/share/deskflow)
+//            config        $XDG_CONFIG_HOME/deskflow (~/.config/deskflow)
+//            cache         $XDG_CACHE_HOME/deskflow  (~/.cache/deskflow)
+//   macOS    data, config  ~/Library/Application Support/deskflow
+//            cache         ~/Library/Caches/deskflow
+//
+// `DESKFLOW_DATA_DIR` overrides the data directory. Older builds kept
+// everything in ~/.deskflow outside Windows; that directory is moved to the
+// data directory the first time it is found.
+// --------------------------------------------------------------------------
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::error::DeskflowError;
+
+const APP_DIR: &str = "deskflow";
+const DB_FILE: &str = "usage_data.db";
+
+#[derive(Serialize, Clone, Debug)]
+pub struct Paths {
+    /// The database, its backups and anything else that is user data.
+    pub data_dir: PathBuf,
+    /// Hand-edited files such as `rules.json` and `settings.toml`.
+    pub config_dir: PathBuf,
+    /// Files that can be thrown away at any time.
+    pub cache_dir: PathBuf,
+}
+
+impl Paths {
+    /// Resolve the directories from the environment of this process.
+    pub fn resolve() -> Result<Self, DeskflowError> {
+        Self::resolve_with(|name| std::env::var_os(name).filter(|v| !v.is_empty()).map(PathBuf::from))
+    }
+
+    /// Resolve the directories, reading environment variables through `env`.
+    pub fn resolve_with(env: impl Fn(&str) -> Option<PathBuf>) -> Result<Self, DeskflowError> {
+        let mut paths = platform_paths(&env)?;
+        if let Some(dir) = env("DESKFLOW_DATA_DIR") {
+            paths.data_dir = dir;
+        }
+        Ok(paths)
+    }
+
+    pub fn db_path(&self) -> PathBuf {
+        self.data_dir.join(DB_FILE)
+    }
+
+    pub fn rules_file(&self) -> PathBuf {
+        self.config_dir.join("rules.json")
+    }
+
+    pub fn settings_file(&self) -> PathBuf {
+        self.config_dir.join("settings.toml")
+    }
+
+    pub fn create_dirs(&self) -> Result<(), DeskflowError> {
+        for dir in [&self.data_dir, &self.config_dir, &self.cache_dir] {
+            fs::create_dir_all(dir).map_err(|e| DeskflowError::io_at(dir, e))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+fn home(env: &impl Fn(&str) -> Option<PathBuf>) -> Result<PathBuf, DeskflowError> {
+    env("HOME").ok_or_else(|| DeskflowError::platform("HOME is not set, cannot tell where to keep data"))
+}
+
+/// `$var/deskflow` if `var` holds an absolute path (XDG says to ignore
+/// relative ones), otherwise `~/<fallback>/deskflow`.
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+fn xdg_dir(env: &impl Fn(&str) -> Option<PathBuf>, var: &str, fallback: &str) -> Result<PathBuf, DeskflowError> {
+    match env(var).filter(|dir| dir.is_absolute()) {
+        Some(dir) => Ok(dir.join(APP_DIR)),
+        None => Ok(home(env)?.join(fallback).join(APP_DIR)),
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn platform_paths(env: &impl Fn(&str) -> Option<PathBuf>) -> Result<Paths, DeskflowError> {
+    let roaming = env("APPDATA").ok_or_else(|| DeskflowError::platform("APPDATA is not set, cannot tell where to keep data"))?;
+    let local = env("LOCALAPPDATA").unwrap_or_else(|| roaming.clone());
+
+    Ok(Paths {
+        data_dir: roaming.join(APP_DIR),
+        config_dir: roaming.join(APP_DIR),
+        cache_dir: local.join(APP_DIR),
+    })
+}
+
+#[cfg(target_os = "macos")]
+fn platform_paths(env: &impl Fn(&str) -> Option<PathBuf>) -> Result<Paths, DeskflowError> {
+    let library = home(env)?.join("Library");
+
+    Ok(Paths {
+        data_dir: library.join("Application Support").join(APP_DIR),
+        config_dir: library.join("Application Support").join(APP_DIR),
+        cache_dir: library.join("Caches").join(APP_DIR),
+    })
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+fn platform_paths(env: &impl Fn(&str) -> Option<PathBuf>) -> Result<Paths, DeskflowError> {
+    Ok(Paths {
+        data_dir: xdg_dir(env, "XDG_DATA_HOME", ".local/share")?,
+        config_dir: xdg_dir(env, "XDG_CONFIG_HOME", ".config")?,
+        cache_dir: xdg_dir(env, "XDG_CACHE_HOME", ".cache")?,
+    })
+}
+
+
+// --------------- ~/.deskflow migration --------------- //
+
+/// Where a migration copies files before moving them into place, one per
+/// target directory so the final move is a rename on the same file system.
+const STAGING_DIR: &str = ".deskflow-migrating";
+
+/// One legacy file on its way to its new place.
+struct Staged {
+    source: PathBuf,
+    staged: PathBuf,
+    target: PathBuf,
+}
+
+/// Move the files of an old `~/.deskflow` into `paths`, unless the data
+/// directory already has a database. `rules.json` goes to the config
+/// directory, everything else to the data directory. Returns whether
+/// anything was moved.
+///
+/// Everything is copied into a staging directory first and only renamed
+/// into place once all copies succeeded; on failure the new directories are
+/// left as they were and the old one is untouched.
+pub fn migrate_legacy_dir(paths: &Paths, legacy: &Path) -> Result<bool, DeskflowError> {
+    if !legacy.join(DB_FILE).exists() || paths.db_path().exists() || legacy == paths.data_dir {
+        return Ok(false);
+    }
+    paths.create_dirs()?;
+
+    let result = stage_legacy_files(paths, legacy).and_then(|staged| {
+        move_into_place(&staged)?;
+        Ok(staged)
+    });
+    for dir in [&paths.data_dir, &paths.config_dir] {
+        let _ = fs::remove_dir_all(dir.join(STAGING_DIR));
+    }
+
+    for file in result? {
+        let _ = fs::remove_file(&file.source);
+    }
+    // Only goes away if everything could be moved
+    let _ = fs::remove_dir(legacy);
+    Ok(true)
+}
+
+/// Copy every file of `legacy` that doesn't exist yet at its new place into
+/// the staging directory next to that place.
+fn stage_legacy_files(paths: &Paths, legacy: &Path) -> Result<Vec<Staged>, DeskflowError> {
+    for dir in [&paths.data_dir, &paths.config_dir] {
+        let staging = dir.join(STAGING_DIR);
+        // Left over from an interrupted attempt
+        let _ = fs::remove_dir_all(&staging);
+        fs::create_dir_all(&staging).map_err(|e| DeskflowError::io_at(&staging, e))?;
+    }
+
+    let mut staged = Vec::new();
+    let entries = fs::read_dir(legacy).map_err(|e| DeskflowError::io_at(legacy, e))?;
+    for entry in entries {
+        let entry = entry.map_err(|e| DeskflowError::io_at(legacy, e))?;
+        let source = entry.path();
+        if !source.is_file() {
+            continue;
+        }
+
+        let name = entry.file_name();
+        let dir = if name == "rules.json" { &paths.config_dir } else { &paths.data_dir };
+        let target = dir.join(&name);
+        if target.exists() {
+            continue;
+        }
+
+        let copy = dir.join(STAGING_DIR).join(&name);
+        fs::copy(&source, &copy).map_err(|e| DeskflowError::io_at(&source, e))?;
+        staged.push(Staged {
+            source,
+            staged: copy,
+            target,
+        });
+    }
+    Ok(staged)
+}
+
+/// Rename the staged copies into place, removing the ones already moved if
+/// one fails.
+fn move_into_place(staged: &[Staged]) -> Result<(), DeskflowError> {
+    for (moved, file) in staged.iter().enumerate() {
+        if let Err(e) = fs::rename(&file.staged, &file.target) {
+            for done in &staged[..moved] {
+                let _ = fs::remove_file(&done.target);
+            }
+            return Err(DeskflowError::io_at(&file.target, e));
+        }
+    }
+    Ok(())
+}
+
+/// `~/.deskflow`, where builds before the XDG layout kept their data. Never
+/// used on Windows, where the location has not changed.
+pub fn legacy_dir() -> Option<PathBuf> {
+    if cfg!(target_os = "windows") {
+        return None;
+    }
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".deskflow"))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use tempfile::TempDir;
+
+    fn resolve(vars: &[(&str, &str)]) -> Result<Paths, DeskflowError> {
+        let vars: HashMap<&str, PathBuf> = vars
+            .iter()
+            .map(|(name, value)| (*name, PathBuf::from(value)))
+            .collect();
+        Paths::resolve_with(|name| vars.get(name).cloned())
+    }
+
+    #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+    #[test]
+    fn linux_defaults_follow_home() {
+        let paths = resolve(&[("HOME", "/home/ada")]).unwrap();
+
+        assert_eq!(paths.data_dir, Path::new("/home/ada/.local/share/deskflow"));
+        assert_eq!(paths.config_dir, Path::new("/home/ada/.config/deskflow"));
+        assert_eq!(paths.cache_dir, Path::new("/home/ada/.cache/deskflow"));
+        assert_eq!(
+            paths.db_path(),
+            Path::new("/home/ada/.local/share/deskflow/usage_data.db")
+        );
+    }
+
+    #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+    #[test]
+    fn xdg_variables_take_precedence() {
+        let paths = resolve(&[
+            ("HOME", "/home/ada"),
+            ("XDG_DATA_HOME", "/data"),
+            ("XDG_CONFIG_HOME", "/etc/ada"),
+        ])
+        .unwrap();
+
+        assert_eq!(paths.data_dir, Path::new("/data/deskflow"));
+        assert_eq!(paths.config_dir, Path::new("/etc/ada/deskflow"));
+        assert_eq!(paths.cache_dir, Path::new("/home/ada/.cache/deskflow"));
+    }
+
+    #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+    #[test]
+    fn relative_xdg_paths_are_ignored() {
+        let paths = resolve(&[("HOME", "/home/ada"), ("XDG_DATA_HOME", "data")]).unwrap();
+        assert_eq!(paths.data_dir, Path::new("/home/ada/.local/share/deskflow"));
+    }
+
+    #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+    #[test]
+    fn data_dir_override_wins() {
+        let paths = resolve(&[
+            ("HOME", "/home/ada"),
+            ("XDG_DATA_HOME", "/data"),
+            ("DESKFLOW_DATA_DIR", "/mnt/usb/deskflow"),
+        ])
+        .unwrap();
+
+        assert_eq!(paths.data_dir, Path::new("/mnt/usb/deskflow"));
+        assert_eq!(paths.config_dir, Path::new("/home/ada/.config/deskflow"));
+    }
+
+    #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+    #[test]
+    fn missing_home_is_an_error() {
+        let Err(err) = resolve(&[("XDG_DATA_HOME", "/data")]) else {
+            panic!("resolved without HOME");
+        };
+        assert_eq!(err.kind(), "platform");
+
+        // Not needed when every XDG directory is set
+        let all_xdg = [
+            ("XDG_DATA_HOME", "/data"),
+            ("XDG_CONFIG_HOME", "/config"),
+            ("XDG_CACHE_HOME", "/cache"),
+        ];
+        assert!(resolve(&all_xdg).is_ok());
+    }
+
+    #[cfg(target_os = "windows")]
+    #[test]
+    fn windows_keeps_data_in_appdata() {
+        let paths = resolve(&[("APPDATA", r"C:\Users\ada\AppData\Roaming")]).unwrap();
+        assert_eq!(paths.data_dir, Path::new(r"C:\Users\ada\AppData\Roaming\deskflow"));
+        assert_eq!(paths.cache_dir, paths.data_dir);
+
+        let Err(err) = resolve(&[]) else {
+            panic!("resolved without APPDATA");
+        };
+        assert_eq!(err.kind(), "platform");
+    }
+
+    #[cfg(target_os = "macos")]
+    #[test]
+    fn macos_uses_the_library() {
+        let paths = resolve(&[("HOME", "/Users/ada"), ("XDG_DATA_HOME", "/data")]).unwrap();
+        assert_eq!(paths.data_dir, Path::new("/Users/ada/Library/Application Support/deskflow"));
+        assert_eq!(paths.cache_dir, Path::new("/Users/ada/Library/Caches/deskflow"));
+    }
+
+    /// An old `~/.deskflow` and the new directories, all under one temp dir.
+    fn layout() -> (TempDir, PathBuf, Paths) {
+        let dir = TempDir::new().unwrap();
+        let legacy = dir.path().join(".deskflow");
+        fs::create_dir(&legacy).unwrap();
+        for (name, contents) in [
+            (DB_FILE, "db"),
+            ("rules.json", "[]"),
+            ("usage_data.db.2024-05-06.bak", "bak"),
+        ] {
+            fs::write(legacy.join(name), contents).unwrap();
+        }
+
+        let paths = Paths {
+            data_dir: dir.path().join("data"),
+            config_dir: dir.path().join("config"),
+            cache_dir: dir.path().join("cache"),
+        };
+        (dir, legacy, paths)
+    }
+
+    fn read(path: &Path) -> String {
+        fs::read_to_string(path).unwrap()
+    }
+
+    #[test]
+    fn legacy_files_move_to_their_new_places() {
+        let (_dir, legacy, paths) = layout();
+
+        assert!(migrate_legacy_dir(&paths, &legacy).unwrap());
+
+        assert_eq!(read(&paths.db_path()), "db");
+        assert_eq!(read(&paths.rules_file()), "[]");
+        assert_eq!(read(&paths.data_dir.join("usage_data.db.2024-05-06.bak")), "bak");
+        assert!(!legacy.exists());
+        assert!(!paths.data_dir.join(STAGING_DIR).exists());
+        assert!(!paths.config_dir.join(STAGING_DIR).exists());
+    }
+
+    #[test]
+    fn existing_files_are_kept() {
+        let (_dir, legacy, paths) = layout();
+        paths.create_dirs().unwrap();
+        fs::write(paths.rules_file(), "[\"mine\"]").unwrap();
+
+        assert!(migrate_legacy_dir(&paths, &legacy).unwrap());
+
+        assert_eq!(read(&paths.rules_file()), "[\"mine\"]");
+        assert_eq!(read(&paths.db_path()), "db");
+        // What wasn't moved stays behind
+        assert_eq!(read(&legacy.join("rules.json")), "[]");
+        assert!(!legacy.join(DB_FILE).exists());
+    }
+
+    #[test]
+    fn nothing_happens_once_there_is_a_database() {
+        let (_dir, legacy, paths) = layout();
+        paths.create_dirs().unwrap();
+        fs::write(paths.db_path(), "new").unwrap();
+
+        assert!(!migrate_legacy_dir(&paths, &legacy).unwrap());
+        assert_eq!(read(&paths.db_path()), "new");
+        assert_eq!(read(&legacy.join(DB_FILE)), "db");
+    }
+
+    #[test]
+    fn a_failed_copy_leaves_everything_as_it_was() {
+        let (_dir, legacy, paths) = layout();
+        paths.create_dirs().unwrap();
+        // A file where the staging directory has to go
+        fs::write(paths.config_dir.join(STAGING_DIR), "").unwrap();
+
+        assert!(migrate_legacy_dir(&paths, &legacy).is_err());
+
+        assert!(!paths.db_path().exists());
+        assert!(!paths.rules_file().exists());
+        assert!(!paths.data_dir.join(STAGING_DIR).exists());
+        for name in [DB_FILE, "rules.json", "usage_data.db.2024-05-06.bak"] {
+            assert!(legacy.join(name).exists(), "{} was removed", name);
+        }
+    }
+
+    #[test]
+    fn a_failed_move_takes_back_the_files_already_moved() {
+        let dir = TempDir::new().unwrap();
+        let staged = |name: &str| Staged {
+            source: dir.path().join("old").join(name),
+            staged: dir.path().join(name),
+            target: dir.path().join("new").join(name),
+        };
+        fs::create_dir(dir.path().join("new")).unwrap();
+        fs::write(dir.path().join("first"), "").unwrap();
+
+        // `second` was never staged, so its rename fails
+        assert!(move_into_place(&[staged("first"), staged("second")]).is_err());
+        assert!(!dir.path().join("new").join("first").exists());
+    }
+}
@@ -0,0 +1,538 @@
+// --------------------------------------------------------------------------
+// Read-only reports over the stored history. Raw `app_usage` rows only go
+// back `RetentionPolicy::raw_days`; anything older is read from the
+// `daily_app_usage` roll-ups, so the calendar covers the whole history. The
+// drill-downs need window titles, which are not rolled up, so they only see
+// the raw rows. Idle time is never counted as tracked time.
+// --------------------------------------------------------------------------
+
+use std::cmp::Reverse;
+use std::collections::HashMap;
+
+use chrono::{Datelike, Duration, NaiveDate};
+use rusqlite::{Connection, Result};
+use serde::Serialize;
+use tauri::State;
+
+use crate::db::Db;
+use crate::error::DeskflowError;
+use crate::scoring::history::load_scores;
+use crate::tracker::IDLE_CATEGORY;
+
+/// Number of entries a top-N query returns when the caller doesn't say.
+pub const DEFAULT_LIMIT: usize = 10;
+
+/// SQL columns summing focused and background seconds of `app_usage` rows.
+const FOCUSED_AND_BACKGROUND: &str =
+    "SUM(CASE WHEN is_focused THEN end_time - start_time ELSE 0 END) AS focused,
+     SUM(CASE WHEN is_focused THEN 0 ELSE end_time - start_time END) AS background";
+
+pub fn parse_date_range(from: &str, to: &str) -> Result<(NaiveDate, NaiveDate), DeskflowError> {
+    let (from, to) = (parse_date(from)?, parse_date(to)?);
+    if from > to {
+        return Err(DeskflowError::config(format!("{} is after {}", from, to)));
+    }
+    Ok((from, to))
+}
+
+/// Parse a `YYYY-MM-DD` date sent by the frontend.
+pub fn parse_date(date: &str) -> Result<NaiveDate, DeskflowError> {
+    NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .map_err(|e| DeskflowError::config(format!("invalid date '{}': {}", date, e)))
+}
+
+// --------------- Calendar heatmap --------------- //
+
+#[derive(Serialize, Debug)]
+pub struct CalendarDay {
+    pub date: String,
+    pub score: Option<f64>,
+    /// Focused seconds, idle excluded.
+    pub total_seconds: u64,
+    /// Category with the most focused time that day.
+    pub dominant_category: Option<String>,
+}
+
+/// Focused seconds per day and category between `from` and `to`, ordered by
+/// date and then by time spent, most first.
+fn daily_category_totals(
+    conn: &Connection,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> Result<Vec<(NaiveDate, String, u64)>> {
+    let mut stmt = conn.prepare_cached(
+        "SELECT date, category, SUM(seconds) AS seconds FROM (
+            SELECT date, category, SUM(end_time - start_time) AS seconds FROM app_usage
+            WHERE date BETWEEN ?1 AND ?2 AND is_focused AND category != ?3
+            GROUP BY date, category
+            UNION ALL
+            SELECT date, category, SUM(focused_seconds) FROM daily_app_usage
+            WHERE date BETWEEN ?1 AND ?2 AND category != ?3
+            GROUP BY date, category
+         )
+         GROUP BY date, category
+         ORDER BY date, seconds DESC, category",
+    )?;
+
+    let rows = stmt.query_map((from.to_string(), to.to_string(), IDLE_CATEGORY), |row| {
+        Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, u64>(2)?))
+    })?;
+
+    let mut totals = Vec::new();
+    for row in rows {
+        let (date, category, seconds) = row?;
+        // Dates are always written as %Y-%m-%d; skip anything that isn't
+        if let Ok(date) = NaiveDate::parse_from_str(&date, "%Y-%m-%d") {
+            totals.push((date, category, seconds));
+        }
+    }
+    Ok(totals)
+}
+
+/// One entry per day from `from` to `to`, with or without data.
+pub fn calendar(conn: &Connection, from: NaiveDate, to: NaiveDate) -> Result<Vec<CalendarDay>> {
+    let scores = load_scores(conn, from, to)?;
+
+    let mut usage: HashMap<NaiveDate, (u64, String)> = HashMap::new();
+    for (date, category, seconds) in daily_category_totals(conn, from, to)? {
+        // Rows arrive biggest first, so the first category seen is the dominant one
+        usage.entry(date).or_insert((0, category)).0 += seconds;
+    }
+
+    let mut days = Vec::new();
+    let mut day = from;
+    while day <= to {
+        let (total_seconds, dominant_category) = match usage.remove(&day) {
+            Some((total, category)) => (total, Some(category)),
+            None => (0, None),
+        };
+
+        days.push(CalendarDay {
+            date: day.to_string(),
+            score: scores.get(&day).copied(),
+            total_seconds,
+            dominant_category,
+        });
+        day += Duration::days(1);
+    }
+    Ok(days)
+}
+
+/// Every day of `month` in `year`, or of the whole year when `month` is
+/// `None`.
+#[tauri::command]
+pub fn get_calendar(db: State<'_, Db>, year: i32, month: Option<u32>) -> Result<Vec<CalendarDay>, DeskflowError> {
+    let (from, to) = match month {
+        Some(month) => {
+            let from = NaiveDate::from_ymd_opt(year, month, 1)
+                .ok_or_else(|| DeskflowError::config(format!("invalid month {}-{}", year, month)))?;
+            let next = if month == 12 {
+                NaiveDate::from_ymd_opt(year + 1, 1, 1)
+            } else {
+                NaiveDate::from_ymd_opt(year, month + 1, 1)
+            };
+            (from, next.map_or(from, |next| next - Duration::days(1)))
+        }
+        None => {
+            let from = NaiveDate::from_ymd_opt(year, 1, 1).ok_or_else(|| DeskflowError::config(format!("invalid year {}", year)))?;
+            (from, from.with_month(12).and_then(|d| d.with_day(31)).unwrap_or(from))
+        }
+    };
+
+    Ok(calendar(&db.lock(), from, to)?)
+}
+
+
+// --------------- Drill-downs --------------- //
+
+/// Time spent in one app, window title, ...
+#[derive(Serialize, Debug)]
+pub struct UsageTotal {
+    pub name: String,
+    pub focused_seconds: u64,
+    pub background_seconds: u64,
+}
+
+#[derive(Serialize, Debug)]
+pub struct AppBreakdown {
+    pub app_name: String,
+    pub focused_seconds: u64,
+    pub background_seconds: u64,
+    pub titles: Vec<UsageTotal>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct CategoryBreakdown {
+    pub category: String,
+    pub focused_seconds: u64,
+    pub background_seconds: u64,
+    pub apps: Vec<AppBreakdown>,
+}
+
+/// Apps with the most focused time between `from` and `to`, optionally only
+/// counting time in `category`.
+pub fn top_apps(
+    conn: &Connection,
+    from: NaiveDate,
+    to: NaiveDate,
+    category: Option<&str>,
+    limit: usize,
+) -> Result<Vec<UsageTotal>> {
+    let mut stmt = conn.prepare_cached(&format!(
+        "SELECT app_name, {FOCUSED_AND_BACKGROUND} FROM app_usage
+         WHERE date BETWEEN ?1 AND ?2 AND category != ?3 AND (?4 IS NULL OR category = ?4)
+         GROUP BY app_name
+         ORDER BY focused DESC, background DESC, app_name
+         LIMIT ?5"
+    ))?;
+
+    let rows = stmt.query_map(
+        (from.to_string(), to.to_string(), IDLE_CATEGORY, category, limit as i64),
+        |row| {
+            Ok(UsageTotal {
+                name: row.get(0)?,
+                focused_seconds: row.get(1)?,
+                background_seconds: row.get(2)?,
+            })
+        },
+    )?;
+    rows.collect()
+}
+
+/// Window titles of `app_name` with the most focused time between `from`
+/// and `to`.
+pub fn top_titles(
+    conn: &Connection,
+    from: NaiveDate,
+    to: NaiveDate,
+    app_name: &str,
+    limit: usize,
+) -> Result<Vec<UsageTotal>> {
+    let mut stmt = conn.prepare_cached(&format!(
+        "SELECT window_title, {FOCUSED_AND_BACKGROUND} FROM app_usage
+         WHERE date BETWEEN ?1 AND ?2 AND category != ?3 AND app_name = ?4
+         GROUP BY window_title
+         ORDER BY focused DESC, background DESC, window_title
+         LIMIT ?5"
+    ))?;
+
+    let rows = stmt.query_map(
+        (from.to_string(), to.to_string(), IDLE_CATEGORY, app_name, limit as i64),
+        |row| {
+            Ok(UsageTotal {
+                name: row.get(0)?,
+                focused_seconds: row.get(1)?,
+                background_seconds: row.get(2)?,
+            })
+        },
+    )?;
+    rows.collect()
+}
+
+/// Category → app → title tree between `from` and `to`, every level sorted
+/// by focused time. Only the top `titles_per_app` titles of each app are
+/// kept; the app totals still include the rest.
+pub fn category_breakdown(
+    conn: &Connection,
+    from: NaiveDate,
+    to: NaiveDate,
+    titles_per_app: usize,
+) -> Result<Vec<CategoryBreakdown>> {
+    let mut stmt = conn.prepare_cached(&format!(
+        "SELECT category, app_name, window_title, {FOCUSED_AND_BACKGROUND} FROM app_usage
+         WHERE date BETWEEN ?1 AND ?2 AND category != ?3
+         GROUP BY category, app_name, window_title
+         ORDER BY category, app_name, focused DESC, background DESC, window_title"
+    ))?;
+
+    let rows = stmt.query_map((from.to_string(), to.to_string(), IDLE_CATEGORY), |row| {
+        Ok((
+            row.get::<_, String>(0)?,
+            row.get::<_, String>(1)?,
+            UsageTotal {
+                name: row.get(2)?,
+                focused_seconds: row.get(3)?,
+                background_seconds: row.get(4)?,
+            },
+        ))
+    })?;
+
+    let mut categories: Vec<CategoryBreakdown> = Vec::new();
+    for row in rows {
+        let (category, app_name, title) = row?;
+
+        // Rows come grouped by category and app, so only the last entry
+        // can be the one this row belongs to
+        if categories.last().map(|c| &c.category) != Some(&category) {
+            categories.push(CategoryBreakdown {
+                category,
+                focused_seconds: 0,
+                background_seconds: 0,
+                apps: Vec::new(),
+            });
+        }
+        let node = categories.last_mut().unwrap();
+        node.focused_seconds += title.focused_seconds;
+        node.background_seconds += title.background_seconds;
+
+        if node.apps.last().map(|a| &a.app_name) != Some(&app_name) {
+            node.apps.push(AppBreakdown {
+                app_name,
+                focused_seconds: 0,
+                background_seconds: 0,
+                titles: Vec::new(),
+            });
+        }
+        let app = node.apps.last_mut().unwrap();
+        app.focused_seconds += title.focused_seconds;
+        app.background_seconds += title.background_seconds;
+        if app.titles.len() < titles_per_app {
+            app.titles.push(title);
+        }
+    }
+
+    for category in &mut categories {
+        category
+            .apps
+            .sort_by_key(|a| Reverse((a.focused_seconds, a.background_seconds)));
+    }
+    categories.sort_by_key(|c| Reverse((c.focused_seconds, c.background_seconds)));
+    Ok(categories)
+}
+
+#[tauri::command]
+pub fn get_top_apps(
+    db: State<'_, Db>,
+    from: String,
+    to: String,
+    category: Option<String>,
+    limit: Option<usize>,
+) -> Result<Vec<UsageTotal>, DeskflowError> {
+    let (from, to) = parse_date_range(&from, &to)?;
+    Ok(top_apps(&db.lock(), from, to, category.as_deref(), limit.unwrap_or(DEFAULT_LIMIT))?)
+}
+
+#[tauri::command]
+pub fn get_top_titles(
+    db: State<'_, Db>,
+    from: String,
+    to: String,
+    app_name: String,
+    limit: Option<usize>,
+) -> Result<Vec<UsageTotal>, DeskflowError> {
+    let (from, to) = parse_date_range(&from, &to)?;
+    Ok(top_titles(&db.lock(), from, to, &app_name, limit.unwrap_or(DEFAULT_LIMIT))?)
+}
+
+#[tauri::command]
+pub fn get_category_breakdown(
+    db: State<'_, Db>,
+    from: String,
+    to: String,
+    titles_per_app: Option<usize>,
+) -> Result<Vec<CategoryBreakdown>, DeskflowError> {
+    let (from, to) = parse_date_range(&from, &to)?;
+    Ok(category_breakdown(&db.lock(), from, to, titles_per_app.unwrap_or(DEFAULT_LIMIT))?)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn database() -> Connection {
+        let mut conn = Connection::open_in_memory().unwrap();
+        crate::migrations::migrate(&mut conn, Path::new(":memory:")).unwrap();
+        conn
+    }
+
+    fn may(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 5, day).unwrap()
+    }
+
+    /// Raw rows on `date`, as (app, title, category, seconds, focused).
+    fn insert_rows(conn: &Connection, date: &str, rows: &[(&str, &str, &str, u64, bool)]) {
+        for (app, title, category, seconds, focused) in rows {
+            conn.execute(
+                "INSERT INTO app_usage (app_name, window_title, category, start_time, end_time, date, is_focused)
+                 VALUES (?1, ?2, ?3, 0, ?4, ?5, ?6)",
+                rusqlite::params![app, title, category, seconds, date, focused],
+            )
+            .unwrap();
+        }
+    }
+
+    fn insert_rollup(
+        conn: &Connection,
+        date: &str,
+        app: &str,
+        category: &str,
+        focused: u64,
+        background: u64,
+    ) {
+        conn.execute(
+            "INSERT INTO daily_app_usage (date, app_name, category, focused_seconds, background_seconds)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            rusqlite::params![date, app, category, focused, background],
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn calendar_reads_raw_and_rolled_up_days() {
+        let conn = database();
+        // May 5 is only left as roll-ups
+        insert_rollup(&conn, "2024-05-05", "code", "Work", 3600, 0);
+        insert_rollup(&conn, "2024-05-05", "spotify", "Music", 600, 7200);
+        insert_rollup(&conn, "2024-05-05", IDLE_CATEGORY, IDLE_CATEGORY, 9000, 0);
+        conn.execute(
+            "INSERT INTO productivity_scores (date, score) VALUES ('2024-05-05', 85.0)",
+            [],
+        )
+        .unwrap();
+        // May 6 still has its raw rows; background time doesn't count
+        insert_rows(
+            &conn,
+            "2024-05-06",
+            &[
+                ("spotify", "Daily Mix", "Music", 120, true),
+                ("code", "main.rs", "Work", 60, true),
+                ("code", "lib.rs", "Work", 30, true),
+                ("chrome", "YouTube", "Entertainment", 500, false),
+                (IDLE_CATEGORY, IDLE_CATEGORY, IDLE_CATEGORY, 1000, true),
+            ],
+        );
+
+        let days = calendar(&conn, may(5), may(7)).unwrap();
+
+        let summary: Vec<_> = days
+            .iter()
+            .map(|day| {
+                (
+                    day.date.as_str(),
+                    day.score,
+                    day.total_seconds,
+                    day.dominant_category.as_deref(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("2024-05-05", Some(85.0), 4200, Some("Work")),
+                ("2024-05-06", None, 210, Some("Music")),
+                ("2024-05-07", None, 0, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn calendar_adds_up_a_day_that_is_partly_rolled_up() {
+        let conn = database();
+        insert_rollup(&conn, "2024-05-06", "code", "Work", 100, 0);
+        insert_rows(
+            &conn,
+            "2024-05-06",
+            &[
+                ("spotify", "Daily Mix", "Music", 150, true),
+                ("code", "main.rs", "Work", 60, true),
+            ],
+        );
+
+        let days = calendar(&conn, may(6), may(6)).unwrap();
+
+        assert_eq!(days[0].total_seconds, 310);
+        assert_eq!(days[0].dominant_category.as_deref(), Some("Work"));
+    }
+
+    /// May 6 as raw rows, plus an idle row and a row outside the range.
+    fn drill_down_data() -> Connection {
+        let conn = database();
+        insert_rows(
+            &conn,
+            "2024-05-06",
+            &[
+                ("code", "main.rs", "Work", 300, true),
+                ("code", "main.rs", "Work", 50, false),
+                ("code", "lib.rs", "Work", 100, true),
+                ("chrome", "Pull requests - GitHub", "Work", 200, true),
+                ("spotify", "Daily Mix", "Music", 20, true),
+                ("spotify", "Daily Mix", "Music", 500, false),
+                (IDLE_CATEGORY, IDLE_CATEGORY, IDLE_CATEGORY, 1000, true),
+            ],
+        );
+        insert_rows(&conn, "2024-05-08", &[("code", "main.rs", "Work", 9999, true)]);
+        conn
+    }
+
+    fn totals(usage: &[UsageTotal]) -> Vec<(&str, u64, u64)> {
+        usage
+            .iter()
+            .map(|total| {
+                (
+                    total.name.as_str(),
+                    total.focused_seconds,
+                    total.background_seconds,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn top_apps_rank_by_focused_time() {
+        let conn = drill_down_data();
+
+        let apps = top_apps(&conn, may(6), may(7), None, DEFAULT_LIMIT).unwrap();
+        assert_eq!(
+            totals(&apps),
+            [("code", 400, 50), ("chrome", 200, 0), ("spotify", 20, 500)]
+        );
+
+        let music = top_apps(&conn, may(6), may(7), Some("Music"), DEFAULT_LIMIT).unwrap();
+        assert_eq!(totals(&music), [("spotify", 20, 500)]);
+
+        let first = top_apps(&conn, may(6), may(7), None, 1).unwrap();
+        assert_eq!(totals(&first), [("code", 400, 50)]);
+    }
+
+    #[test]
+    fn top_titles_stay_within_the_app() {
+        let conn = drill_down_data();
+
+        let titles = top_titles(&conn, may(6), may(7), "code", DEFAULT_LIMIT).unwrap();
+        assert_eq!(totals(&titles), [("main.rs", 300, 50), ("lib.rs", 100, 0)]);
+    }
+
+    #[test]
+    fn breakdown_totals_add_up_to_the_rows() {
+        let conn = drill_down_data();
+
+        let breakdown = category_breakdown(&conn, may(6), may(7), 1).unwrap();
+
+        let categories: Vec<_> = breakdown
+            .iter()
+            .map(|c| (c.category.as_str(), c.focused_seconds, c.background_seconds))
+            .collect();
+        assert_eq!(categories, [("Work", 600, 50), ("Music", 20, 500)]);
+
+        let work = &breakdown[0];
+        let apps: Vec<_> = work
+            .apps
+            .iter()
+            .map(|a| (a.app_name.as_str(), a.focused_seconds, a.background_seconds))
+            .collect();
+        assert_eq!(apps, [("code", 400, 50), ("chrome", 200, 0)]);
+        // Only the top title is listed, but the app total still counts lib.rs
+        assert_eq!(totals(&work.apps[0].titles), [("main.rs", 300, 50)]);
+
+        for category in &breakdown {
+            let focused: u64 = category.apps.iter().map(|a| a.focused_seconds).sum();
+            let background: u64 = category.apps.iter().map(|a| a.background_seconds).sum();
+            assert_eq!(
+                (focused, background),
+                (category.focused_seconds, category.background_seconds)
+            );
+        }
+    }
+}
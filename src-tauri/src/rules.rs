@@ -0,0 +1,1160 @@
+// --------------------------------------------------------------------------
+// Rule-based categorization. A rule assigns a category when all of its
+// conditions match a window's title and/or process name; when several rules
+// match, the highest priority wins and ties go to the rule listed first.
+//
+// Rules are stored in the `categorization_rules` table and can be edited at
+// runtime through the commands at the bottom of this file; the tracker picks
+// up the new set on its next tick. An empty table is seeded from
+// `rules.json` next to the database if present, otherwise from the defaults.
+// --------------------------------------------------------------------------
+
+use glob::{MatchOptions, Pattern as GlobPattern};
+use regex::{Regex, RegexBuilder};
+use rusqlite::types::Type;
+use rusqlite::{params, Connection, Transaction};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+use tauri::State;
+
+use crate::db::Db;
+use crate::error::DeskflowError;
+use crate::reports::parse_date_range;
+use crate::tracker::IDLE_CATEGORY;
+
+/// Category of a window no rule matched.
+pub const FALLBACK_CATEGORY: &str = "Other";
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Rule {
+    pub id: String,
+    pub category: String,
+    #[serde(default)]
+    pub priority: i32,
+    /// Every condition has to match for the rule to apply.
+    pub conditions: Vec<Condition>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Condition {
+    pub field: Field,
+    #[serde(flatten)]
+    pub pattern: Pattern,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Field {
+    Title,
+    Process,
+    /// Title and process name joined by a space.
+    Any,
+}
+
+/// All comparisons ignore case.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Pattern {
+    Equals(String),
+    Contains(String),
+    Regex(String),
+    Glob(String),
+}
+
+/// Outcome of categorizing one window.
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct Categorization {
+    pub category: String,
+    /// `None` when no rule matched and the fallback category was used.
+    pub rule_id: Option<String>,
+}
+
+enum Matcher {
+    Equals(String),
+    Contains(String),
+    Regex(Regex),
+    Glob(GlobPattern),
+}
+
+impl Matcher {
+    fn compile(pattern: &Pattern) -> Result<Self, DeskflowError> {
+        Ok(match pattern {
+            Pattern::Equals(value) => Matcher::Equals(value.to_lowercase()),
+            Pattern::Contains(value) => Matcher::Contains(value.to_lowercase()),
+            Pattern::Regex(value) => Matcher::Regex(
+                RegexBuilder::new(value)
+                    .case_insensitive(true)
+                    .build()
+                    .map_err(|e| DeskflowError::config(e.to_string()))?,
+            ),
+            Pattern::Glob(value) => {
+                Matcher::Glob(GlobPattern::new(value).map_err(|e| DeskflowError::config(e.to_string()))?)
+            }
+        })
+    }
+
+    /// `lowered` is `text` in lowercase, computed once per window.
+    fn is_match(&self, text: &str, lowered: &str) -> bool {
+        match self {
+            Matcher::Equals(value) => lowered == value,
+            Matcher::Contains(value) => lowered.contains(value.as_str()),
+            Matcher::Regex(re) => re.is_match(text),
+            Matcher::Glob(glob) => glob.matches_with(
+                text,
+                MatchOptions {
+                    case_sensitive: false,
+                    ..MatchOptions::new()
+                },
+            ),
+        }
+    }
+}
+
+struct CompiledRule {
+    id: String,
+    category: String,
+    priority: i32,
+    conditions: Vec<(Field, Matcher)>,
+}
+
+/// A validated, ready-to-match set of rules.
+pub struct Categorizer {
+    /// Sorted by descending priority, file order within a priority.
+    rules: Vec<CompiledRule>,
+}
+
+impl Categorizer {
+    /// Compile `rules`, failing on the first invalid pattern.
+    pub fn new(rules: &[Rule]) -> Result<Self, DeskflowError> {
+        let mut compiled = rules
+            .iter()
+            .map(|rule| {
+                let conditions = rule
+                    .conditions
+                    .iter()
+                    .map(|c| Ok((c.field, Matcher::compile(&c.pattern)?)))
+                    .collect::<Result<Vec<_>, DeskflowError>>()
+                    .map_err(|e| DeskflowError::config(format!("rule '{}': {}", rule.id, e)))?;
+
+                Ok(CompiledRule {
+                    id: rule.id.clone(),
+                    category: rule.category.clone(),
+                    priority: rule.priority,
+                    conditions,
+                })
+            })
+            .collect::<Result<Vec<_>, DeskflowError>>()?;
+
+        // Stable sort keeps file order for equal priorities
+        compiled.sort_by_key(|rule| std::cmp::Reverse(rule.priority));
+
+        Ok(Self { rules: compiled })
+    }
+
+    pub fn categorize(&self, title: &str, process: &str) -> Categorization {
+        let any = format!("{} {}", title, process);
+        let lowered_title = title.to_lowercase();
+        let lowered_process = process.to_lowercase();
+        let lowered_any = any.to_lowercase();
+
+        let matched = self.rules.iter().find(|rule| {
+            rule.conditions.iter().all(|(field, matcher)| match field {
+                Field::Title => matcher.is_match(title, &lowered_title),
+                Field::Process => matcher.is_match(process, &lowered_process),
+                Field::Any => matcher.is_match(&any, &lowered_any),
+            })
+        });
+
+        match matched {
+            Some(rule) => Categorization {
+                category: rule.category.clone(),
+                rule_id: Some(rule.id.clone()),
+            },
+            None => Categorization {
+                category: FALLBACK_CATEGORY.to_string(),
+                rule_id: None,
+            },
+        }
+    }
+}
+
+/// Read a JSON array of rules from `path`.
+pub fn read_rules_file(path: &Path) -> Result<Vec<Rule>, DeskflowError> {
+    let contents = fs::read_to_string(path).map_err(|e| DeskflowError::io_at(path, e))?;
+    serde_json::from_str(&contents).map_err(|e| DeskflowError::config(format!("{}: {}", path.display(), e)))
+}
+
+fn rule(id: &str, category: &str, priority: i32, conditions: Vec<Condition>) -> Rule {
+    Rule {
+        id: id.to_string(),
+        category: category.to_string(),
+        priority,
+        conditions,
+    }
+}
+
+fn when(field: Field, pattern: Pattern) -> Condition {
+    Condition { field, pattern }
+}
+
+const BROWSER: &str = r"^(chrome|brave)(\.exe)?$";
+
+/// The categories DeskFlow has always used, as rules.
+pub fn default_rules() -> Vec<Rule> {
+    use Field::*;
+    use Pattern::*;
+
+    let browser = || when(Process, Regex(BROWSER.to_string()));
+
+    vec![
+        rule("spotify", "Music", 100, vec![when(Any, Contains("spotify".into()))]),
+        rule("vscode", "Work", 90, vec![when(Process, Regex(r"^(code|codium)(\.exe)?$".into()))]),
+        rule(
+            "browser-video",
+            "Entertainment",
+            80,
+            vec![browser(), when(Title, Regex("youtube|netflix".into()))],
+        ),
+        rule(
+            "browser-work",
+            "Work",
+            70,
+            vec![browser(), when(Title, Regex("docs|chatgpt|slack".into()))],
+        ),
+        rule(
+            "browser-code-hosting",
+            "Work",
+            70,
+            vec![browser(), when(Title, Regex("github|gitlab|bitbucket".into()))],
+        ),
+        rule(
+            "browser-research",
+            "Research",
+            70,
+            vec![browser(), when(Title, Regex("research|papers|arxiv".into()))],
+        ),
+        rule(
+            "browser-education",
+            "Education",
+            70,
+            vec![browser(), when(Title, Regex("education|learning|courses".into()))],
+        ),
+        rule("browser", "Browsing", 60, vec![browser()]),
+        rule("games", "Gaming", 50, vec![when(Any, Contains("game".into()))]),
+        rule(
+            "chat",
+            "Chatting",
+            40,
+            vec![when(Any, Regex("whatsapp|discord|teams|telegram".into()))],
+        ),
+    ]
+}
+
+
+// --------------- Persistence --------------- //
+
+/// All stored rules, in the order they were added.
+pub fn load_rules(conn: &Connection) -> rusqlite::Result<Vec<Rule>> {
+    let mut stmt = conn.prepare_cached(
+        "SELECT id, category, priority, conditions FROM categorization_rules ORDER BY rowid",
+    )?;
+
+    let rows = stmt.query_map([], |row| {
+        let conditions: String = row.get(3)?;
+        Ok(Rule {
+            id: row.get(0)?,
+            category: row.get(1)?,
+            priority: row.get(2)?,
+            conditions: serde_json::from_str(&conditions)
+                .map_err(|e| rusqlite::Error::FromSqlConversionFailure(3, Type::Text, Box::new(e)))?,
+        })
+    })?;
+
+    rows.collect()
+}
+
+pub fn insert_rule(conn: &Connection, rule: &Rule) -> rusqlite::Result<()> {
+    let conditions = serde_json::to_string(&rule.conditions)
+        .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+
+    conn.execute(
+        "INSERT INTO categorization_rules (id, category, priority, conditions) VALUES (?1, ?2, ?3, ?4)",
+        params![rule.id, rule.category, rule.priority, conditions],
+    )?;
+    Ok(())
+}
+
+/// Fill an empty rules table from `rules_file` if it exists, otherwise from
+/// the built-in defaults.
+pub fn seed_rules(conn: &mut Connection, rules_file: &Path) -> Result<(), DeskflowError> {
+    let count: i64 = conn.query_row("SELECT COUNT(*) FROM categorization_rules", [], |row| row.get(0))?;
+    if count > 0 {
+        return Ok(());
+    }
+
+    let rules = if rules_file.exists() {
+        read_rules_file(rules_file)?
+    } else {
+        default_rules()
+    };
+    Categorizer::new(&rules)?;
+
+    let tx = conn.transaction()?;
+    for rule in &rules {
+        insert_rule(&tx, rule)?;
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+
+// --------------- Live rule set --------------- //
+
+/// The categorizer the tracker uses, swapped out whenever the rules change.
+#[derive(Clone)]
+pub struct ActiveRules {
+    current: Arc<RwLock<Arc<Categorizer>>>,
+}
+
+impl ActiveRules {
+    pub fn new(categorizer: Categorizer) -> Self {
+        Self {
+            current: Arc::new(RwLock::new(Arc::new(categorizer))),
+        }
+    }
+
+    /// Compile the stored rules. A broken rule set falls back to the
+    /// built-in rules rather than stopping the tracker.
+    pub fn load(conn: &Connection) -> Self {
+        let categorizer = load_rules(conn)
+            .map_err(DeskflowError::from)
+            .and_then(|rules| Categorizer::new(&rules))
+            .unwrap_or_else(|e| {
+                eprintln!("Using built-in categorization rules: {}", e);
+                Categorizer::new(&default_rules()).expect("built-in rules are valid")
+            });
+
+        Self::new(categorizer)
+    }
+
+    pub fn current(&self) -> Arc<Categorizer> {
+        self.current.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    pub fn replace(&self, categorizer: Categorizer) {
+        *self.current.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(categorizer);
+    }
+}
+
+fn validate(rule: &Rule) -> Result<(), DeskflowError> {
+    if rule.id.trim().is_empty() {
+        return Err(DeskflowError::config("rule id must not be empty"));
+    }
+    if rule.category.trim().is_empty() {
+        return Err(DeskflowError::config(format!("rule '{}': category must not be empty", rule.id)));
+    }
+    if rule.conditions.is_empty() {
+        return Err(DeskflowError::config(format!("rule '{}': needs at least one condition", rule.id)));
+    }
+    Ok(())
+}
+
+fn duplicate_id(e: rusqlite::Error, id: &str) -> DeskflowError {
+    match e {
+        rusqlite::Error::SqliteFailure(err, _) if err.code == rusqlite::ErrorCode::ConstraintViolation => {
+            DeskflowError::config(format!("a rule with id '{}' already exists", id))
+        }
+        other => other.into(),
+    }
+}
+
+fn create_rule(conn: &Connection, rule: &Rule) -> Result<(), DeskflowError> {
+    insert_rule(conn, rule).map_err(|e| duplicate_id(e, &rule.id))
+}
+
+/// Replace the rule stored under `id`. A new id is carried over to the
+/// sessions the rule already categorized, so none point at a rule that no
+/// longer exists.
+fn replace_rule(conn: &Connection, id: &str, rule: &Rule) -> Result<(), DeskflowError> {
+    let conditions = serde_json::to_string(&rule.conditions)?;
+    let updated = conn
+        .execute(
+            "UPDATE categorization_rules SET id = ?1, category = ?2, priority = ?3, conditions = ?4 WHERE id = ?5",
+            params![rule.id, rule.category, rule.priority, conditions, id],
+        )
+        .map_err(|e| duplicate_id(e, &rule.id))?;
+
+    if updated == 0 {
+        return Err(DeskflowError::config(format!("no rule with id '{}'", id)));
+    }
+    if rule.id != id {
+        conn.execute(
+            "UPDATE app_usage SET rule_id = ?1 WHERE rule_id = ?2",
+            params![rule.id, id],
+        )?;
+    }
+    Ok(())
+}
+
+fn remove_rule(conn: &Connection, id: &str) -> Result<(), DeskflowError> {
+    let deleted = conn.execute("DELETE FROM categorization_rules WHERE id = ?1", [id])?;
+
+    if deleted == 0 {
+        return Err(DeskflowError::config(format!("no rule with id '{}'", id)));
+    }
+    Ok(())
+}
+
+/// Apply `change` to the stored rules and make the result live. The whole
+/// new set has to compile, otherwise nothing is written.
+fn change_rules(
+    db: &Db,
+    active: &ActiveRules,
+    change: impl FnOnce(&Transaction) -> Result<(), DeskflowError>,
+) -> Result<(), DeskflowError> {
+    let mut conn = db.lock();
+    let tx = conn.transaction()?;
+
+    change(&tx)?;
+    let rules = load_rules(&tx)?;
+    let categorizer = Categorizer::new(&rules)?;
+
+    tx.commit()?;
+    active.replace(categorizer);
+    Ok(())
+}
+
+
+// --------------- Re-categorization --------------- //
+
+/// Rows moved into and out of one category by a re-categorization.
+#[derive(Serialize, Debug, Default, Clone, Copy)]
+pub struct CategoryDelta {
+    pub gained: usize,
+    pub lost: usize,
+}
+
+/// Rows of one window that move from one category to another.
+#[derive(Serialize, Debug, Clone)]
+pub struct CategoryChange {
+    pub app_name: String,
+    pub window_title: String,
+    pub old_category: String,
+    pub new_category: String,
+    pub new_rule_id: Option<String>,
+    pub rows: usize,
+}
+
+#[derive(Serialize, Debug, Default)]
+pub struct RecategorizeReport {
+    pub dry_run: bool,
+    /// Rows whose category or rule id was (or would be) rewritten.
+    pub rows_updated: usize,
+    pub by_category: HashMap<String, CategoryDelta>,
+    pub changes: Vec<CategoryChange>,
+}
+
+/// Run `categorizer` over the raw sessions dated `from..=to` and rewrite the
+/// category and rule id of every row that comes out differently, all in one
+/// transaction. With `dry_run` the transaction is rolled back and only the
+/// report is returned. Idle rows and rolled-up days are left alone.
+pub fn recategorize(
+    conn: &mut Connection,
+    categorizer: &Categorizer,
+    from: NaiveDate,
+    to: NaiveDate,
+    dry_run: bool,
+) -> rusqlite::Result<RecategorizeReport> {
+    let tx = conn.transaction()?;
+    let mut report = RecategorizeReport {
+        dry_run,
+        ..Default::default()
+    };
+
+    let rows = {
+        let mut stmt = tx.prepare(
+            "SELECT id, app_name, window_title, category, rule_id FROM app_usage
+             WHERE date BETWEEN ?1 AND ?2 AND category != ?3",
+        )?;
+        let rows = stmt.query_map(params![from.to_string(), to.to_string(), IDLE_CATEGORY], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, String>(3)?,
+                row.get::<_, Option<String>>(4)?,
+            ))
+        })?;
+        rows.collect::<rusqlite::Result<Vec<_>>>()?
+    };
+
+    // The same window shows up in hundreds of rows; categorize it once
+    let mut cache: HashMap<(String, String), Categorization> = HashMap::new();
+    let mut changes: HashMap<(String, String, String, String), CategoryChange> = HashMap::new();
+
+    {
+        let mut update = tx.prepare("UPDATE app_usage SET category = ?1, rule_id = ?2 WHERE id = ?3")?;
+
+        for (id, app_name, window_title, category, rule_id) in rows {
+            let new = cache
+                .entry((window_title.clone(), app_name.clone()))
+                .or_insert_with(|| categorizer.categorize(&window_title, &app_name));
+
+            if new.category == category && new.rule_id == rule_id {
+                continue;
+            }
+
+            report.rows_updated += 1;
+            if !dry_run {
+                update.execute(params![new.category, new.rule_id, id])?;
+            }
+
+            if new.category != category {
+                report.by_category.entry(new.category.clone()).or_default().gained += 1;
+                report.by_category.entry(category.clone()).or_default().lost += 1;
+
+                let new_category = new.category.clone();
+                let new_rule_id = new.rule_id.clone();
+                changes
+                    .entry((app_name.clone(), window_title.clone(), category.clone(), new_category.clone()))
+                    .or_insert_with(|| CategoryChange {
+                        app_name,
+                        window_title,
+                        old_category: category,
+                        new_category,
+                        new_rule_id,
+                        rows: 0,
+                    })
+                    .rows += 1;
+            }
+        }
+    }
+
+    report.changes = changes.into_values().collect();
+    report.changes.sort_by_key(|change| std::cmp::Reverse(change.rows));
+
+    if !dry_run {
+        tx.commit()?;
+    }
+    Ok(report)
+}
+
+
+// --------------- Commands --------------- //
+
+#[tauri::command]
+pub fn list_rules(db: State<'_, Db>) -> Result<Vec<Rule>, DeskflowError> {
+    Ok(load_rules(&db.lock())?)
+}
+
+#[tauri::command]
+pub fn add_rule(db: State<'_, Db>, active: State<'_, ActiveRules>, rule: Rule) -> Result<Rule, DeskflowError> {
+    validate(&rule)?;
+    change_rules(&db, &active, |tx| create_rule(tx, &rule))?;
+    Ok(rule)
+}
+
+/// Replace the rule stored under `id` with `rule`, which may carry a new id.
+#[tauri::command]
+pub fn update_rule(
+    db: State<'_, Db>,
+    active: State<'_, ActiveRules>,
+    id: String,
+    rule: Rule,
+) -> Result<Rule, DeskflowError> {
+    validate(&rule)?;
+    change_rules(&db, &active, |tx| replace_rule(tx, &id, &rule))?;
+    Ok(rule)
+}
+
+#[tauri::command]
+pub fn delete_rule(db: State<'_, Db>, active: State<'_, ActiveRules>, id: String) -> Result<(), DeskflowError> {
+    change_rules(&db, &active, |tx| remove_rule(tx, &id))
+}
+
+/// How the current rules would categorize a window.
+#[tauri::command]
+pub fn test_rule(active: State<'_, ActiveRules>, title: String, process: String) -> Categorization {
+    active.current().categorize(&title, &process)
+}
+
+/// Re-apply the current rules to sessions dated `from`..=`to` (YYYY-MM-DD).
+#[tauri::command]
+pub fn recategorize_sessions(
+    db: State<'_, Db>,
+    active: State<'_, ActiveRules>,
+    from: String,
+    to: String,
+    dry_run: bool,
+) -> Result<RecategorizeReport, DeskflowError> {
+    let (from, to) = parse_date_range(&from, &to)?;
+    Ok(recategorize(&mut db.lock(), &active.current(), from, to, dry_run)?)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{save_sessions, AppSession};
+    use tempfile::TempDir;
+
+    fn categorizer(rules: Vec<Rule>) -> Categorizer {
+        Categorizer::new(&rules).expect("rules compile")
+    }
+
+    fn category_of(categorizer: &Categorizer, title: &str, process: &str) -> (String, Option<String>) {
+        let result = categorizer.categorize(title, process);
+        (result.category, result.rule_id)
+    }
+
+    fn compile_error(rules: Vec<Rule>) -> String {
+        let Err(err) = Categorizer::new(&rules) else {
+            panic!("rules should not compile");
+        };
+        err.to_string()
+    }
+
+    #[test]
+    fn higher_priority_wins_regardless_of_order() {
+        let rules = categorizer(vec![
+            rule(
+                "low",
+                "Browsing",
+                10,
+                vec![when(Field::Process, Pattern::Equals("chrome".into()))],
+            ),
+            rule(
+                "high",
+                "Work",
+                50,
+                vec![when(Field::Title, Pattern::Contains("github".into()))],
+            ),
+        ]);
+
+        assert_eq!(
+            category_of(&rules, "Pull requests - GitHub", "chrome"),
+            ("Work".to_string(), Some("high".to_string()))
+        );
+        assert_eq!(
+            category_of(&rules, "News", "chrome"),
+            ("Browsing".to_string(), Some("low".to_string()))
+        );
+    }
+
+    #[test]
+    fn ties_go_to_the_rule_listed_first() {
+        let first = rule(
+            "first",
+            "Work",
+            10,
+            vec![when(Field::Any, Pattern::Contains("docs".into()))],
+        );
+        let second = rule(
+            "second",
+            "Research",
+            10,
+            vec![when(Field::Any, Pattern::Contains("docs".into()))],
+        );
+
+        let rules = categorizer(vec![first.clone(), second.clone()]);
+        assert_eq!(category_of(&rules, "docs", "chrome").1.as_deref(), Some("first"));
+
+        let rules = categorizer(vec![second, first]);
+        assert_eq!(category_of(&rules, "docs", "chrome").1.as_deref(), Some("second"));
+    }
+
+    #[test]
+    fn every_condition_has_to_match() {
+        let rules = categorizer(vec![rule(
+            "video",
+            "Entertainment",
+            0,
+            vec![
+                when(Field::Process, Pattern::Equals("chrome".into())),
+                when(Field::Title, Pattern::Contains("youtube".into())),
+            ],
+        )]);
+
+        assert_eq!(category_of(&rules, "YouTube", "chrome").0, "Entertainment");
+        assert_eq!(category_of(&rules, "YouTube", "firefox").0, FALLBACK_CATEGORY);
+        assert_eq!(category_of(&rules, "Inbox", "chrome").0, FALLBACK_CATEGORY);
+    }
+
+    #[test]
+    fn unmatched_windows_fall_back() {
+        let rules = categorizer(default_rules());
+        assert_eq!(
+            category_of(&rules, "Calculator", "calc"),
+            (FALLBACK_CATEGORY.to_string(), None)
+        );
+    }
+
+    #[test]
+    fn equals_matches_the_whole_value_ignoring_case() {
+        let rules = categorizer(vec![rule(
+            "code",
+            "Work",
+            0,
+            vec![when(Field::Process, Pattern::Equals("Code".into()))],
+        )]);
+
+        assert_eq!(category_of(&rules, "main.rs", "code").0, "Work");
+        assert_eq!(category_of(&rules, "main.rs", "CODE").0, "Work");
+        assert_eq!(category_of(&rules, "main.rs", "code.exe").0, FALLBACK_CATEGORY);
+    }
+
+    #[test]
+    fn contains_matches_anywhere_ignoring_case() {
+        let rules = categorizer(vec![rule(
+            "spotify",
+            "Music",
+            0,
+            vec![when(Field::Any, Pattern::Contains("SPOTIFY".into()))],
+        )]);
+
+        assert_eq!(category_of(&rules, "Spotify Premium", "app").0, "Music");
+        // `Any` also looks at the process name
+        assert_eq!(category_of(&rules, "Daily Mix", "spotify.exe").0, "Music");
+        assert_eq!(category_of(&rules, "Daily Mix", "player").0, FALLBACK_CATEGORY);
+    }
+
+    #[test]
+    fn regex_matches_ignoring_case() {
+        let rules = categorizer(vec![rule(
+            "editor",
+            "Work",
+            0,
+            vec![when(
+                Field::Process,
+                Pattern::Regex(r"^(code|codium)(\.exe)?$".into()),
+            )],
+        )]);
+
+        assert_eq!(category_of(&rules, "", "Code.exe").0, "Work");
+        assert_eq!(category_of(&rules, "", "codium").0, "Work");
+        assert_eq!(category_of(&rules, "", "vscode").0, FALLBACK_CATEGORY);
+    }
+
+    #[test]
+    fn glob_matches_the_whole_value_ignoring_case() {
+        let rules = categorizer(vec![rule(
+            "steam",
+            "Gaming",
+            0,
+            vec![when(Field::Title, Pattern::Glob("steam*".into()))],
+        )]);
+
+        assert_eq!(category_of(&rules, "Steam Library", "steam").0, "Gaming");
+        assert_eq!(
+            category_of(&rules, "My steam account", "chrome").0,
+            FALLBACK_CATEGORY
+        );
+    }
+
+    #[test]
+    fn invalid_patterns_are_rejected_with_the_rule_id() {
+        let err = compile_error(vec![rule(
+            "broken-regex",
+            "Work",
+            0,
+            vec![when(Field::Title, Pattern::Regex("(".into()))],
+        )]);
+        assert!(err.contains("broken-regex"), "{}", err);
+
+        let err = compile_error(vec![rule(
+            "broken-glob",
+            "Work",
+            0,
+            vec![when(Field::Title, Pattern::Glob("[a".into()))],
+        )]);
+        assert!(err.contains("broken-glob"), "{}", err);
+    }
+
+    #[test]
+    fn default_rules_compile() {
+        let rules = categorizer(default_rules());
+        assert_eq!(category_of(&rules, "Pull requests - GitHub", "chrome").0, "Work");
+        assert_eq!(
+            category_of(&rules, "Lo-fi - YouTube", "brave.exe").0,
+            "Entertainment"
+        );
+    }
+
+    fn database() -> (TempDir, Db) {
+        let dir = TempDir::new().unwrap();
+        let db = Db::open(&dir.path().join("usage_data.db")).unwrap();
+        for rule in default_rules() {
+            insert_rule(&db.lock(), &rule).unwrap();
+        }
+        (dir, db)
+    }
+
+    fn session(title: &str, process: &str, start: u64, rule_id: Option<&str>) -> AppSession {
+        AppSession {
+            app_name: process.to_string(),
+            window_title: title.to_string(),
+            category: "Work".to_string(),
+            start_time: start,
+            end_time: start + 5,
+            is_focused: true,
+            rule_id: rule_id.map(str::to_string),
+        }
+    }
+
+    fn stored_ids(conn: &Connection) -> Vec<String> {
+        load_rules(conn)
+            .unwrap()
+            .into_iter()
+            .map(|rule| rule.id)
+            .collect()
+    }
+
+    fn config_error(result: Result<(), DeskflowError>) -> String {
+        match result {
+            Err(err @ DeskflowError::Config(_)) => err.to_string(),
+            other => panic!("expected a config error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn validation_rejects_incomplete_rules() {
+        let editor = || {
+            rule(
+                "editor",
+                "Work",
+                0,
+                vec![when(Field::Process, Pattern::Equals("code".into()))],
+            )
+        };
+        assert!(validate(&editor()).is_ok());
+
+        let blank_id = Rule {
+            id: "  ".into(),
+            ..editor()
+        };
+        assert!(config_error(validate(&blank_id)).contains("id"));
+
+        let blank_category = Rule {
+            category: "".into(),
+            ..editor()
+        };
+        assert!(config_error(validate(&blank_category)).contains("category"));
+
+        let no_conditions = Rule {
+            conditions: Vec::new(),
+            ..editor()
+        };
+        assert!(config_error(validate(&no_conditions)).contains("condition"));
+    }
+
+    #[test]
+    fn adding_a_duplicate_id_is_rejected() {
+        let (_dir, db) = database();
+        let active = ActiveRules::load(&db.lock());
+        let duplicate = rule(
+            "vscode",
+            "Work",
+            0,
+            vec![when(Field::Title, Pattern::Contains("x".into()))],
+        );
+
+        let err = config_error(change_rules(&db, &active, |tx| create_rule(tx, &duplicate)));
+        assert!(err.contains("already exists"), "{}", err);
+        assert_eq!(stored_ids(&db.lock()).len(), default_rules().len());
+    }
+
+    #[test]
+    fn a_rule_that_does_not_compile_is_not_stored() {
+        let (_dir, db) = database();
+        let active = ActiveRules::load(&db.lock());
+        let broken = rule(
+            "broken",
+            "Work",
+            1000,
+            vec![when(Field::Title, Pattern::Regex("(".into()))],
+        );
+
+        let err = config_error(change_rules(&db, &active, |tx| create_rule(tx, &broken)));
+        assert!(err.contains("broken"), "{}", err);
+        assert!(!stored_ids(&db.lock()).contains(&"broken".to_string()));
+        assert_eq!(
+            active.current().categorize("main.rs", "code").rule_id.as_deref(),
+            Some("vscode")
+        );
+    }
+
+    #[test]
+    fn changes_go_live() {
+        let (_dir, db) = database();
+        let active = ActiveRules::load(&db.lock());
+        let notes = rule(
+            "notes",
+            "Writing",
+            1000,
+            vec![when(Field::Process, Pattern::Equals("code".into()))],
+        );
+
+        change_rules(&db, &active, |tx| create_rule(tx, &notes)).unwrap();
+        assert_eq!(active.current().categorize("main.rs", "code").category, "Writing");
+
+        change_rules(&db, &active, |tx| remove_rule(tx, "notes")).unwrap();
+        assert_eq!(active.current().categorize("main.rs", "code").category, "Work");
+    }
+
+    #[test]
+    fn missing_rules_cannot_be_updated_or_deleted() {
+        let (_dir, db) = database();
+        let active = ActiveRules::load(&db.lock());
+        let ghost = rule(
+            "ghost",
+            "Work",
+            0,
+            vec![when(Field::Title, Pattern::Contains("x".into()))],
+        );
+
+        let err = config_error(change_rules(&db, &active, |tx| replace_rule(tx, "ghost", &ghost)));
+        assert!(err.contains("no rule with id 'ghost'"), "{}", err);
+
+        let err = config_error(change_rules(&db, &active, |tx| remove_rule(tx, "ghost")));
+        assert!(err.contains("no rule with id 'ghost'"), "{}", err);
+    }
+
+    #[test]
+    fn renaming_a_rule_carries_its_sessions_along() {
+        let (_dir, db) = database();
+        let active = ActiveRules::load(&db.lock());
+        save_sessions(
+            &mut db.lock(),
+            &[
+                session("main.rs", "code", 1_714_989_600, Some("vscode")),
+                session("Inbox", "chrome", 1_714_989_605, Some("browser")),
+            ],
+        )
+        .unwrap();
+
+        let renamed = Rule {
+            id: "editor".into(),
+            ..default_rules()
+                .into_iter()
+                .find(|rule| rule.id == "vscode")
+                .unwrap()
+        };
+        change_rules(&db, &active, |tx| replace_rule(tx, "vscode", &renamed)).unwrap();
+
+        let conn = db.lock();
+        let ids: Vec<Option<String>> = conn
+            .prepare("SELECT rule_id FROM app_usage ORDER BY start_time")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        assert_eq!(ids, vec![Some("editor".to_string()), Some("browser".to_string())]);
+        assert!(stored_ids(&conn).contains(&"editor".to_string()));
+        assert!(!stored_ids(&conn).contains(&"vscode".to_string()));
+    }
+
+    #[test]
+    fn renaming_onto_an_existing_id_is_rejected() {
+        let (_dir, db) = database();
+        let active = ActiveRules::load(&db.lock());
+        save_sessions(
+            &mut db.lock(),
+            &[session("main.rs", "code", 1_714_989_600, Some("vscode"))],
+        )
+        .unwrap();
+
+        let clash = rule(
+            "spotify",
+            "Work",
+            0,
+            vec![when(Field::Process, Pattern::Equals("code".into()))],
+        );
+        let err = config_error(change_rules(&db, &active, |tx| {
+            replace_rule(tx, "vscode", &clash)
+        }));
+        assert!(err.contains("already exists"), "{}", err);
+
+        let rule_id: Option<String> = db
+            .lock()
+            .query_row("SELECT rule_id FROM app_usage", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(rule_id.as_deref(), Some("vscode"));
+    }
+
+    fn migrated() -> Connection {
+        let mut conn = Connection::open_in_memory().unwrap();
+        crate::migrations::migrate(&mut conn, Path::new(":memory:")).unwrap();
+        conn
+    }
+
+    fn insert_row(
+        conn: &Connection,
+        process: &str,
+        title: &str,
+        category: &str,
+        rule_id: Option<&str>,
+        date: &str,
+    ) {
+        conn.execute(
+            "INSERT INTO app_usage (app_name, window_title, category, start_time, end_time, date, is_focused, rule_id)
+             VALUES (?1, ?2, ?3, 0, 5, ?4, 1, ?5)",
+            params![process, title, category, date, rule_id],
+        )
+        .unwrap();
+    }
+
+    /// Sessions recorded before the rules were edited.
+    fn stale_history() -> Connection {
+        let conn = migrated();
+        for _ in 0..3 {
+            insert_row(&conn, "code", "main.rs", "Other", None, "2024-05-06");
+        }
+        insert_row(
+            &conn,
+            "chrome",
+            "Lo-fi - YouTube",
+            "Browsing",
+            Some("browser"),
+            "2024-05-06",
+        );
+        insert_row(
+            &conn,
+            "chrome",
+            "Lo-fi - YouTube",
+            "Browsing",
+            Some("browser"),
+            "2024-05-07",
+        );
+        // Already right, idle, or outside the range: left alone
+        insert_row(
+            &conn,
+            "spotify",
+            "Daily Mix",
+            "Music",
+            Some("spotify"),
+            "2024-05-06",
+        );
+        insert_row(
+            &conn,
+            IDLE_CATEGORY,
+            IDLE_CATEGORY,
+            IDLE_CATEGORY,
+            None,
+            "2024-05-06",
+        );
+        insert_row(&conn, "code", "lib.rs", "Other", None, "2024-05-08");
+        conn
+    }
+
+    fn rows(conn: &Connection) -> Vec<(String, String, Option<String>)> {
+        conn.prepare("SELECT window_title, category, rule_id FROM app_usage ORDER BY id")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap()
+    }
+
+    /// Rows updated, (category, gained, lost) and (title, new category, rows).
+    type Summary = (usize, Vec<(String, usize, usize)>, Vec<(String, String, usize)>);
+
+    fn summary(report: &RecategorizeReport) -> Summary {
+        let mut by_category: Vec<_> = report
+            .by_category
+            .iter()
+            .map(|(category, delta)| (category.clone(), delta.gained, delta.lost))
+            .collect();
+        by_category.sort();
+        let mut changes: Vec<_> = report
+            .changes
+            .iter()
+            .map(|change| {
+                (
+                    change.window_title.clone(),
+                    change.new_category.clone(),
+                    change.rows,
+                )
+            })
+            .collect();
+        changes.sort();
+        (report.rows_updated, by_category, changes)
+    }
+
+    fn may_6_to_7() -> (NaiveDate, NaiveDate) {
+        (
+            NaiveDate::from_ymd_opt(2024, 5, 6).unwrap(),
+            NaiveDate::from_ymd_opt(2024, 5, 7).unwrap(),
+        )
+    }
+
+    #[test]
+    fn a_dry_run_writes_nothing() {
+        let mut conn = stale_history();
+        let before = rows(&conn);
+        let (from, to) = may_6_to_7();
+
+        let report = recategorize(&mut conn, &categorizer(default_rules()), from, to, true).unwrap();
+
+        assert!(report.dry_run);
+        assert_eq!(report.rows_updated, 5);
+        assert_eq!(rows(&conn), before);
+    }
+
+    #[test]
+    fn a_dry_run_reports_what_a_real_run_changes() {
+        let (from, to) = may_6_to_7();
+        let rules = categorizer(default_rules());
+
+        let mut dry = stale_history();
+        let preview = recategorize(&mut dry, &rules, from, to, true).unwrap();
+        let mut real = stale_history();
+        let before = rows(&real);
+        let applied = recategorize(&mut real, &rules, from, to, false).unwrap();
+
+        assert!(!applied.dry_run);
+        assert_eq!(summary(&preview), summary(&applied));
+        assert_eq!(
+            summary(&applied),
+            (
+                5,
+                vec![
+                    ("Browsing".to_string(), 0, 2),
+                    ("Entertainment".to_string(), 2, 0),
+                    ("Other".to_string(), 0, 3),
+                    ("Work".to_string(), 3, 0),
+                ],
+                vec![
+                    ("Lo-fi - YouTube".to_string(), "Entertainment".to_string(), 2),
+                    ("main.rs".to_string(), "Work".to_string(), 3),
+                ],
+            )
+        );
+
+        // Exactly the reported rows changed, and they now match the rules
+        let after = rows(&real);
+        let changed = before.iter().zip(&after).filter(|(old, new)| old != new).count();
+        assert_eq!(changed, applied.rows_updated);
+        assert_eq!(
+            after[0],
+            (
+                "main.rs".to_string(),
+                "Work".to_string(),
+                Some("vscode".to_string())
+            )
+        );
+        assert_eq!(after[7], before[7]);
+
+        // Running again finds nothing left to do
+        assert_eq!(
+            recategorize(&mut real, &rules, from, to, false)
+                .unwrap()
+                .rows_updated,
+            0
+        );
+    }
+}
@@ -0,0 +1,202 @@
+// --------------------------------------------------------------------------
+// Score history for a date range: one entry per calendar day, whether or not
+// a score was stored for it, plus rolling averages and streaks. This is what
+// the history chart and the calendar view are drawn from.
+// --------------------------------------------------------------------------
+
+use std::collections::BTreeMap;
+
+use chrono::{Duration, NaiveDate};
+use rusqlite::types::Type;
+use rusqlite::{Connection, Error, Result};
+use serde::Serialize;
+use tauri::State;
+
+use crate::days::DayBoundary;
+use crate::db::Db;
+use crate::error::DeskflowError;
+use crate::reports::parse_date_range;
+
+/// Score a day needs to extend a streak when the caller doesn't pass one.
+pub const DEFAULT_STREAK_THRESHOLD: f64 = 70.0;
+
+#[derive(Serialize, Debug)]
+pub struct DayScore {
+    pub date: String,
+    pub score: Option<f64>,
+    /// No score is stored for this day (nothing tracked, or not over yet).
+    pub missing: bool,
+    /// Mean of the stored scores in the 7 days ending on this one.
+    pub avg_7: Option<f64>,
+    /// Mean of the stored scores in the 30 days ending on this one.
+    pub avg_30: Option<f64>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ScoreHistory {
+    pub days: Vec<DayScore>,
+    pub threshold: f64,
+    /// Days in a row at or above `threshold`, ending on the last day of the range.
+    pub current_streak: u32,
+    /// Longest run of such days inside the range.
+    pub longest_streak: u32,
+}
+
+/// Stored scores between `from` and `to`, inclusive.
+pub fn load_scores(conn: &Connection, from: NaiveDate, to: NaiveDate) -> Result<BTreeMap<NaiveDate, f64>> {
+    let mut stmt = conn.prepare_cached(
+        "SELECT date, score FROM productivity_scores WHERE date BETWEEN ?1 AND ?2",
+    )?;
+
+    let rows = stmt.query_map((from.to_string(), to.to_string()), |row| {
+        let date: String = row.get(0)?;
+        let date = NaiveDate::parse_from_str(&date, "%Y-%m-%d")
+            .map_err(|e| Error::FromSqlConversionFailure(0, Type::Text, Box::new(e)))?;
+        Ok((date, row.get(1)?))
+    })?;
+    rows.collect()
+}
+
+/// Lay `scores` out over every day from `from` to `to`. `scores` should
+/// reach 29 days further back than `from` so the averages of the first
+/// days are complete.
+///
+/// `today` is never stored until it is over, so a missing score for it
+/// does not break the current streak.
+pub fn build_history(
+    scores: &BTreeMap<NaiveDate, f64>,
+    from: NaiveDate,
+    to: NaiveDate,
+    threshold: f64,
+    today: NaiveDate,
+) -> ScoreHistory {
+    let average = |end: NaiveDate, days: i64| {
+        let window: Vec<f64> = scores
+            .range(end - Duration::days(days - 1)..=end)
+            .map(|(_, score)| *score)
+            .collect();
+        if window.is_empty() {
+            None
+        } else {
+            Some((window.iter().sum::<f64>() / window.len() as f64 * 10.0).round() / 10.0)
+        }
+    };
+
+    let mut days = Vec::new();
+    let mut run = 0;
+    let mut longest_streak = 0;
+    let mut day = from;
+
+    while day <= to {
+        let score = scores.get(&day).copied();
+
+        match score {
+            Some(score) if score >= threshold => {
+                run += 1;
+                longest_streak = longest_streak.max(run);
+            }
+            None if day == today => {}
+            _ => run = 0,
+        }
+
+        days.push(DayScore {
+            date: day.to_string(),
+            score,
+            missing: score.is_none(),
+            avg_7: average(day, 7),
+            avg_30: average(day, 30),
+        });
+        day += Duration::days(1);
+    }
+
+    ScoreHistory {
+        days,
+        threshold,
+        current_streak: run,
+        longest_streak,
+    }
+}
+
+#[tauri::command]
+pub fn get_scores(
+    db: State<'_, Db>,
+    from: String,
+    to: String,
+    threshold: Option<f64>,
+) -> Result<ScoreHistory, DeskflowError> {
+    let (from, to) = parse_date_range(&from, &to)?;
+
+    let conn = db.lock();
+    let scores = load_scores(&conn, from - Duration::days(29), to)?;
+    let today = DayBoundary::load(&conn)?.today();
+    let threshold = threshold.unwrap_or(DEFAULT_STREAK_THRESHOLD);
+
+    Ok(build_history(&scores, from, to, threshold, today))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 5, day).unwrap()
+    }
+
+    fn scores(days: &[(u32, f64)]) -> BTreeMap<NaiveDate, f64> {
+        days.iter().map(|(day, score)| (date(*day), *score)).collect()
+    }
+
+    #[test]
+    fn every_day_is_listed_and_missing_ones_marked() {
+        let history = build_history(&scores(&[(1, 80.0), (3, 60.0)]), date(1), date(3), 70.0, date(10));
+
+        let listed: Vec<_> = history.days.iter().map(|d| (d.date.as_str(), d.score, d.missing)).collect();
+        assert_eq!(
+            listed,
+            vec![("2024-05-01", Some(80.0), false), ("2024-05-02", None, true), ("2024-05-03", Some(60.0), false)]
+        );
+    }
+
+    #[test]
+    fn averages_skip_missing_days() {
+        // Scores on the 1st to 3rd and the 9th, nothing in between
+        let stored = scores(&[(1, 50.0), (2, 60.0), (3, 70.0), (9, 91.0)]);
+        let history = build_history(&stored, date(7), date(9), 70.0, date(20));
+
+        let day = |d: u32| history.days.iter().find(|s| s.date == date(d).to_string()).unwrap();
+        // The 7 days ending on the 7th reach back to the 1st
+        assert_eq!(day(7).avg_7, Some(60.0));
+        assert_eq!(day(8).avg_7, Some(65.0));
+        assert_eq!(day(9).avg_7, Some(80.5));
+        assert_eq!(day(9).avg_30, Some(67.8));
+    }
+
+    #[test]
+    fn averages_are_none_without_scores() {
+        let history = build_history(&BTreeMap::new(), date(1), date(2), 70.0, date(20));
+
+        assert!(history.days.iter().all(|d| d.avg_7.is_none() && d.avg_30.is_none()));
+    }
+
+    #[test]
+    fn streaks_break_on_missing_and_low_days() {
+        let stored = scores(&[(1, 70.0), (2, 90.0), (3, 75.0), (5, 80.0), (6, 50.0), (7, 72.0), (8, 71.0)]);
+        let history = build_history(&stored, date(1), date(8), 70.0, date(20));
+
+        assert_eq!(history.longest_streak, 3);
+        assert_eq!(history.current_streak, 2);
+    }
+
+    #[test]
+    fn today_does_not_break_the_current_streak() {
+        let stored = scores(&[(1, 90.0), (2, 90.0)]);
+
+        let history = build_history(&stored, date(1), date(3), 70.0, date(3));
+        assert_eq!(history.current_streak, 2);
+
+        // The same gap on a finished day does
+        let history = build_history(&stored, date(1), date(3), 70.0, date(4));
+        assert_eq!(history.current_streak, 0);
+    }
+}
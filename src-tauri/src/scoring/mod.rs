@@ -0,0 +1,332 @@
+// --------------------------------------------------------------------------
+// Productivity score. Every category is productive, neutral or distracting,
+// each class has a weight, and the selected `ScoringModel` turns the day's
+// focused sessions into a percentage. Today's score is computed on demand;
+// past days are finalized into `productivity_scores` by the background job
+// once the day is over, whether or not the HUD was open.
+// --------------------------------------------------------------------------
+
+use std::collections::HashMap;
+
+use chrono::{Duration, NaiveDate};
+use rusqlite::{Connection, Result};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, State};
+
+use crate::days::DayBoundary;
+use crate::db::{get_state, set_state, store_productivity_score, Db};
+use crate::error::DeskflowError;
+use crate::settings::{announce, ActiveSettings};
+use crate::tracker::IDLE_CATEGORY;
+
+pub mod history;
+pub mod models;
+
+pub use models::{DeepWorkModel, FocusedSession, LateNightModel, RatioModel, ScoringModel, WeightedModel};
+
+pub const CONFIG_KEY: &str = "scoring_config";
+const LAST_FINALIZED_KEY: &str = "last_finalized_score_date";
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ModelKind {
+    Ratio,
+    #[default]
+    Weighted,
+    DeepWork,
+    LateNight,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ScoringConfig {
+    #[serde(default)]
+    pub model: ModelKind,
+    pub productive: Vec<String>,
+    pub neutral: Vec<String>,
+    /// Listed for clarity; any category not in `productive` or `neutral`
+    /// is treated as distracting.
+    pub distracting: Vec<String>,
+    pub weights: ClassWeights,
+    /// Per-category weights that take precedence over the class weight.
+    #[serde(default)]
+    pub category_weights: HashMap<String, f64>,
+    #[serde(default)]
+    pub deep_work: DeepWorkParams,
+    #[serde(default)]
+    pub late_night: LateNightParams,
+}
+
+/// Share of a second that counts toward the score, per class.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct ClassWeights {
+    pub productive: f64,
+    pub neutral: f64,
+    pub distracting: f64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct DeepWorkParams {
+    /// Shortest uninterrupted productive stretch that counts as deep work.
+    pub min_minutes: u64,
+    /// Points added when the whole day is deep work.
+    pub bonus: f64,
+}
+
+impl Default for DeepWorkParams {
+    fn default() -> Self {
+        Self {
+            min_minutes: 30,
+            bonus: 10.0,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct LateNightParams {
+    /// Local hour the late-night window opens, 0-23.
+    pub start_hour: u32,
+    /// Local hour it closes; may be earlier than `start_hour`.
+    pub end_hour: u32,
+    /// Points taken off per hour of focused time inside the window.
+    pub penalty_per_hour: f64,
+}
+
+impl Default for LateNightParams {
+    fn default() -> Self {
+        Self {
+            start_hour: 23,
+            end_hour: 5,
+            penalty_per_hour: 5.0,
+        }
+    }
+}
+
+impl Default for ScoringConfig {
+    fn default() -> Self {
+        let names = |list: &[&str]| list.iter().map(|s| s.to_string()).collect();
+
+        Self {
+            model: ModelKind::default(),
+            productive: names(&["Development", "Education", "Work", "Writing", "Research", "Tools", "Design"]),
+            neutral: Vec::new(),
+            distracting: names(&["Entertainment", "Gaming", "Browsing", "Chatting", "Music"]),
+            weights: ClassWeights {
+                productive: 1.0,
+                neutral: 0.5,
+                distracting: 0.0,
+            },
+            category_weights: HashMap::new(),
+            deep_work: DeepWorkParams::default(),
+            late_night: LateNightParams::default(),
+        }
+    }
+}
+
+impl ScoringConfig {
+    /// The selected model with this config's parameters.
+    pub fn model(&self) -> Box<dyn ScoringModel> {
+        match self.model {
+            ModelKind::Ratio => Box::new(RatioModel {
+                productive: self.productive.clone(),
+            }),
+            ModelKind::Weighted => Box::new(self.weighted_model()),
+            ModelKind::DeepWork => Box::new(DeepWorkModel {
+                base: self.weighted_model(),
+                productive: self.productive.clone(),
+                min_stretch: self.deep_work.min_minutes * 60,
+                bonus: self.deep_work.bonus,
+            }),
+            ModelKind::LateNight => Box::new(LateNightModel {
+                base: self.weighted_model(),
+                start_hour: self.late_night.start_hour,
+                end_hour: self.late_night.end_hour,
+                penalty_per_hour: self.late_night.penalty_per_hour,
+            }),
+        }
+    }
+
+    fn weighted_model(&self) -> WeightedModel {
+        let mut weights = HashMap::new();
+        for category in &self.distracting {
+            weights.insert(category.clone(), self.weights.distracting);
+        }
+        for category in &self.neutral {
+            weights.insert(category.clone(), self.weights.neutral);
+        }
+        for category in &self.productive {
+            weights.insert(category.clone(), self.weights.productive);
+        }
+        weights.extend(self.category_weights.iter().map(|(c, w)| (c.clone(), *w)));
+
+        WeightedModel {
+            weights,
+            default_weight: self.weights.distracting,
+        }
+    }
+
+    fn validate(&self) -> Result<(), DeskflowError> {
+        let weights = [self.weights.productive, self.weights.neutral, self.weights.distracting];
+        if weights.iter().chain(self.category_weights.values()).any(|w| !(0.0..=1.0).contains(w)) {
+            return Err(DeskflowError::config("weights must be between 0 and 1"));
+        }
+        if self.deep_work.min_minutes == 0 {
+            return Err(DeskflowError::config("deep work stretches must be at least a minute long"));
+        }
+        if !(0.0..=100.0).contains(&self.deep_work.bonus) || !(0.0..=100.0).contains(&self.late_night.penalty_per_hour) {
+            return Err(DeskflowError::config("bonus and penalty must be between 0 and 100 points"));
+        }
+        if self.late_night.start_hour > 23 || self.late_night.end_hour > 23 {
+            return Err(DeskflowError::config("late-night hours must be between 0 and 23"));
+        }
+        Ok(())
+    }
+}
+
+pub fn load_config(conn: &Connection) -> Result<ScoringConfig> {
+    Ok(get_state(conn, CONFIG_KEY)?
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default())
+}
+
+/// Focused, non-idle sessions of `date` in the order they started.
+pub fn load_focused_sessions(conn: &Connection, date: NaiveDate) -> Result<Vec<FocusedSession>> {
+    let mut stmt = conn.prepare_cached(
+        "SELECT category, start_time, end_time FROM app_usage
+         WHERE date = ?1 AND is_focused AND category != ?2
+         ORDER BY start_time",
+    )?;
+
+    let rows = stmt.query_map((date.to_string(), IDLE_CATEGORY), |row| {
+        Ok(FocusedSession {
+            category: row.get(0)?,
+            start_time: row.get(1)?,
+            end_time: row.get(2)?,
+        })
+    })?;
+    rows.collect()
+}
+
+/// Score for `date` in percent (0-100, one decimal), or `None` if nothing
+/// but idle time was recorded that day.
+pub fn compute_score(conn: &Connection, model: &dyn ScoringModel, date: NaiveDate) -> Result<Option<f64>> {
+    let sessions = load_focused_sessions(conn, date)?;
+    Ok(model
+        .score(&sessions)
+        .map(|score| (score.clamp(0.0, 100.0) * 10.0).round() / 10.0))
+}
+
+/// Store the final score of every day after the last finalized one, up to
+/// and including yesterday. Days without data are skipped.
+pub fn finalize_scores(conn: &Connection, today: NaiveDate) -> Result<()> {
+    let yesterday = today - Duration::days(1);
+
+    let mut day = match get_state(conn, LAST_FINALIZED_KEY)?
+        .and_then(|date| NaiveDate::parse_from_str(&date, "%Y-%m-%d").ok())
+    {
+        Some(last) => last + Duration::days(1),
+        None => yesterday,
+    };
+    if day > yesterday {
+        return Ok(());
+    }
+
+    let model = load_config(conn)?.model();
+    while day <= yesterday {
+        if let Some(score) = compute_score(conn, model.as_ref(), day)? {
+            store_productivity_score(conn, &day.to_string(), score, model.name(), model.version())?;
+        }
+        day += Duration::days(1);
+    }
+
+    set_state(conn, LAST_FINALIZED_KEY, &yesterday.to_string())
+}
+
+
+// --------------- Commands --------------- //
+
+/// Live score for today; `None` until something has been tracked.
+#[tauri::command]
+pub fn get_today_score(db: State<'_, Db>) -> Result<Option<f64>, DeskflowError> {
+    let conn = db.lock();
+    let model = load_config(&conn)?.model();
+    let today = DayBoundary::load(&conn)?.today();
+    Ok(compute_score(&conn, model.as_ref(), today)?)
+}
+
+#[tauri::command]
+pub fn get_scoring_config(db: State<'_, Db>) -> Result<ScoringConfig, DeskflowError> {
+    Ok(load_config(&db.lock())?)
+}
+
+#[tauri::command]
+pub fn set_scoring_config(
+    app: AppHandle,
+    db: State<'_, Db>,
+    active: State<'_, ActiveSettings>,
+    config: ScoringConfig,
+) -> Result<(), DeskflowError> {
+    config.validate()?;
+    let json = serde_json::to_string(&config)?;
+    set_state(&db.lock(), CONFIG_KEY, &json)?;
+    announce(&app, &active);
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rejected(change: impl FnOnce(&mut ScoringConfig)) -> bool {
+        let mut config = ScoringConfig::default();
+        change(&mut config);
+        config.validate().is_err()
+    }
+
+    #[test]
+    fn default_config_is_valid() {
+        assert!(ScoringConfig::default().validate().is_ok());
+    }
+
+    #[test]
+    fn validation_rejects_out_of_range_values() {
+        assert!(rejected(|c| c.weights.productive = 1.5));
+        assert!(rejected(|c| c.weights.distracting = -0.1));
+        assert!(rejected(|c| {
+            c.category_weights.insert("Work".to_string(), 2.0);
+        }));
+        assert!(rejected(|c| c.deep_work.min_minutes = 0));
+        assert!(rejected(|c| c.deep_work.bonus = 101.0));
+        assert!(rejected(|c| c.late_night.penalty_per_hour = -1.0));
+        assert!(rejected(|c| c.late_night.start_hour = 24));
+        assert!(rejected(|c| c.late_night.end_hour = 24));
+    }
+
+    #[test]
+    fn category_weights_override_classes() {
+        let mut config = ScoringConfig::default();
+        config.category_weights.insert("Music".to_string(), 0.25);
+        let model = config.weighted_model();
+
+        assert_eq!(model.weights["Work"], 1.0);
+        assert_eq!(model.weights["Music"], 0.25);
+        assert_eq!(model.weights.get("Unknown"), None);
+        assert_eq!(model.default_weight, config.weights.distracting);
+    }
+
+    #[test]
+    fn config_picks_its_model() {
+        for (kind, name) in [
+            (ModelKind::Ratio, "ratio"),
+            (ModelKind::Weighted, "weighted"),
+            (ModelKind::DeepWork, "deep_work"),
+            (ModelKind::LateNight, "late_night"),
+        ] {
+            let config = ScoringConfig {
+                model: kind,
+                ..ScoringConfig::default()
+            };
+            assert_eq!(config.model().name(), name);
+        }
+    }
+}
@@ -0,0 +1,361 @@
+// --------------------------------------------------------------------------
+// Built-in scoring models. A model turns one day of focused sessions into a
+// score in percent; the caller clamps and rounds it. Every stored score
+// records the model's name and version, so bump `version` whenever a change
+// would score the same day differently.
+// --------------------------------------------------------------------------
+
+use std::collections::HashMap;
+
+use chrono::{Local, TimeZone};
+
+use crate::analytics::{distribute, Bucket};
+
+/// Productive sessions this close together still count as one stretch.
+const STRETCH_GAP: u64 = 60;
+
+#[derive(Clone, Debug)]
+pub struct FocusedSession {
+    pub category: String,
+    pub start_time: u64,
+    pub end_time: u64,
+}
+
+impl FocusedSession {
+    fn seconds(&self) -> u64 {
+        self.end_time.saturating_sub(self.start_time)
+    }
+}
+
+pub trait ScoringModel: Send + Sync {
+    fn name(&self) -> &'static str;
+    fn version(&self) -> u32;
+    /// Score for one day's focused sessions, sorted by start time, or `None`
+    /// when there is nothing to score.
+    fn score(&self, sessions: &[FocusedSession]) -> Option<f64>;
+}
+
+fn total_seconds(sessions: &[FocusedSession]) -> u64 {
+    sessions.iter().map(FocusedSession::seconds).sum()
+}
+
+/// Productive seconds over all focused seconds.
+pub struct RatioModel {
+    pub productive: Vec<String>,
+}
+
+impl ScoringModel for RatioModel {
+    fn name(&self) -> &'static str {
+        "ratio"
+    }
+
+    fn version(&self) -> u32 {
+        1
+    }
+
+    fn score(&self, sessions: &[FocusedSession]) -> Option<f64> {
+        let total = total_seconds(sessions);
+        if total == 0 {
+            return None;
+        }
+
+        let productive: u64 = sessions
+            .iter()
+            .filter(|s| self.productive.contains(&s.category))
+            .map(FocusedSession::seconds)
+            .sum();
+
+        Some(productive as f64 / total as f64 * 100.0)
+    }
+}
+
+/// Every category counts with its own weight between 0 and 1.
+pub struct WeightedModel {
+    pub weights: HashMap<String, f64>,
+    /// Weight of categories missing from `weights`.
+    pub default_weight: f64,
+}
+
+impl WeightedModel {
+    fn weight(&self, category: &str) -> f64 {
+        self.weights.get(category).copied().unwrap_or(self.default_weight)
+    }
+}
+
+impl ScoringModel for WeightedModel {
+    fn name(&self) -> &'static str {
+        "weighted"
+    }
+
+    fn version(&self) -> u32 {
+        1
+    }
+
+    fn score(&self, sessions: &[FocusedSession]) -> Option<f64> {
+        let total = total_seconds(sessions);
+        if total == 0 {
+            return None;
+        }
+
+        let weighted: f64 = sessions
+            .iter()
+            .map(|s| self.weight(&s.category) * s.seconds() as f64)
+            .sum();
+
+        Some(weighted / total as f64 * 100.0)
+    }
+}
+
+/// The weighted score plus up to `bonus` points, in proportion to the share
+/// of the day spent in uninterrupted productive stretches of at least
+/// `min_stretch` seconds.
+pub struct DeepWorkModel {
+    pub base: WeightedModel,
+    pub productive: Vec<String>,
+    pub min_stretch: u64,
+    pub bonus: f64,
+}
+
+impl DeepWorkModel {
+    fn deep_seconds(&self, sessions: &[FocusedSession]) -> u64 {
+        let mut deep = 0;
+        let mut stretch = 0;
+        let mut last_end: Option<u64> = None;
+
+        for session in sessions {
+            let productive = self.productive.contains(&session.category);
+            let continues = last_end.is_some_and(|end| session.start_time <= end + STRETCH_GAP);
+
+            if !productive || !continues {
+                if stretch >= self.min_stretch {
+                    deep += stretch;
+                }
+                stretch = 0;
+            }
+
+            if productive {
+                stretch += session.seconds();
+                last_end = Some(session.end_time);
+            } else {
+                last_end = None;
+            }
+        }
+
+        if stretch >= self.min_stretch {
+            deep += stretch;
+        }
+        deep
+    }
+}
+
+impl ScoringModel for DeepWorkModel {
+    fn name(&self) -> &'static str {
+        "deep_work"
+    }
+
+    fn version(&self) -> u32 {
+        1
+    }
+
+    fn score(&self, sessions: &[FocusedSession]) -> Option<f64> {
+        let base = self.base.score(sessions)?;
+        let share = self.deep_seconds(sessions) as f64 / total_seconds(sessions) as f64;
+        Some(base + self.bonus * share)
+    }
+}
+
+/// The weighted score minus `penalty_per_hour` points for every hour of
+/// focused time between `start_hour` and `end_hour` (local time, wrapping
+/// past midnight). Sessions are cut at hour boundaries, so only the part of
+/// a session inside the window counts.
+pub struct LateNightModel {
+    pub base: WeightedModel,
+    pub start_hour: u32,
+    pub end_hour: u32,
+    pub penalty_per_hour: f64,
+}
+
+impl LateNightModel {
+    fn is_late(&self, hour: u32) -> bool {
+        if self.start_hour <= self.end_hour {
+            (self.start_hour..self.end_hour).contains(&hour)
+        } else {
+            hour >= self.start_hour || hour < self.end_hour
+        }
+    }
+
+    /// Focused seconds inside the window, with hours taken in `tz`.
+    fn late_seconds<Tz: TimeZone>(&self, tz: &Tz, sessions: &[FocusedSession]) -> u64 {
+        let rows: Vec<_> = sessions
+            .iter()
+            .map(|s| (String::new(), s.start_time, s.end_time))
+            .collect();
+
+        distribute(tz, &rows, Bucket::HourOfDay)
+            .values()
+            .flat_map(|hours| hours.iter().enumerate())
+            .filter(|(hour, _)| self.is_late(*hour as u32))
+            .map(|(_, seconds)| seconds)
+            .sum()
+    }
+
+    fn score_in<Tz: TimeZone>(&self, tz: &Tz, sessions: &[FocusedSession]) -> Option<f64> {
+        let base = self.base.score(sessions)?;
+        let late = self.late_seconds(tz, sessions);
+        Some(base - self.penalty_per_hour * late as f64 / 3600.0)
+    }
+}
+
+impl ScoringModel for LateNightModel {
+    fn name(&self) -> &'static str {
+        "late_night"
+    }
+
+    /// 2: late time is the overlap with the window, no longer whole
+    /// sessions by their start.
+    fn version(&self) -> u32 {
+        2
+    }
+
+    fn score(&self, sessions: &[FocusedSession]) -> Option<f64> {
+        self.score_in(&Local, sessions)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{FixedOffset, Utc};
+
+    const HOUR: u64 = 3600;
+
+    fn session(category: &str, start: u64, end: u64) -> FocusedSession {
+        FocusedSession {
+            category: category.to_string(),
+            start_time: start,
+            end_time: end,
+        }
+    }
+
+    fn names(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    fn weighted() -> WeightedModel {
+        WeightedModel {
+            weights: HashMap::from([("Work".to_string(), 1.0), ("Chatting".to_string(), 0.5)]),
+            default_weight: 0.0,
+        }
+    }
+
+    fn deep_work() -> DeepWorkModel {
+        DeepWorkModel {
+            base: weighted(),
+            productive: names(&["Work"]),
+            min_stretch: 30 * 60,
+            bonus: 10.0,
+        }
+    }
+
+    fn late_night() -> LateNightModel {
+        LateNightModel {
+            base: weighted(),
+            start_hour: 23,
+            end_hour: 5,
+            penalty_per_hour: 5.0,
+        }
+    }
+
+    /// Unix time of `hour:minute` on 2024-05-06 UTC.
+    fn at(hour: u64, minute: u64) -> u64 {
+        1_714_953_600 + hour * HOUR + minute * 60
+    }
+
+    #[test]
+    fn nothing_to_score() {
+        assert_eq!(RatioModel { productive: names(&["Work"]) }.score(&[]), None);
+        assert_eq!(weighted().score(&[session("Work", 10, 10)]), None);
+        assert_eq!(deep_work().score(&[]), None);
+        assert_eq!(late_night().score(&[]), None);
+    }
+
+    #[test]
+    fn ratio_counts_productive_share() {
+        let model = RatioModel {
+            productive: names(&["Work"]),
+        };
+        let sessions = [session("Work", 0, 300), session("Chatting", 300, 400)];
+
+        assert_eq!(model.score(&sessions), Some(75.0));
+    }
+
+    #[test]
+    fn weighted_uses_category_and_default_weights() {
+        let sessions = [
+            session("Work", 0, 100),
+            session("Chatting", 100, 200),
+            session("Gaming", 200, 400),
+        ];
+
+        // (100 * 1.0 + 100 * 0.5 + 200 * 0.0) / 400
+        assert_eq!(weighted().score(&sessions), Some(37.5));
+    }
+
+    #[test]
+    fn deep_work_adds_bonus_for_long_stretches() {
+        // 40 productive minutes in 5-second rows, then 20 minutes of chat
+        let mut sessions: Vec<_> = (0..480).map(|i| session("Work", i * 5, i * 5 + 5)).collect();
+        sessions.push(session("Chatting", 2400, 3600));
+
+        // weighted: (2400 + 0.5 * 1200) / 3600 = 83.33, plus 10 * 2400 / 3600
+        let score = deep_work().score(&sessions).unwrap();
+        assert!((score - (250.0 / 3.0 + 20.0 / 3.0)).abs() < 1e-9, "{score}");
+    }
+
+    #[test]
+    fn deep_work_stretch_survives_short_gaps() {
+        // Two 20-minute blocks of work a minute apart make one 40-minute stretch
+        let close = [session("Work", 0, 1200), session("Work", 1200 + STRETCH_GAP, 2400 + STRETCH_GAP)];
+        assert_eq!(deep_work().deep_seconds(&close), 2400);
+
+        // A second more and neither block is long enough
+        let apart = [session("Work", 0, 1200), session("Work", 1201 + STRETCH_GAP, 2401 + STRETCH_GAP)];
+        assert_eq!(deep_work().deep_seconds(&apart), 0);
+
+        // Anything unproductive in between breaks the stretch
+        let interrupted = [session("Work", 0, 1200), session("Gaming", 1200, 1210), session("Work", 1210, 2400)];
+        assert_eq!(deep_work().deep_seconds(&interrupted), 0);
+    }
+
+    #[test]
+    fn late_night_counts_only_the_overlap() {
+        // 21:50 to 02:00: three hours of it fall between 23:00 and 05:00
+        let sessions = [session("Work", at(21, 50), at(26, 0))];
+
+        assert_eq!(late_night().late_seconds(&Utc, &sessions), 3 * HOUR);
+        assert_eq!(late_night().score_in(&Utc, &sessions), Some(100.0 - 3.0 * 5.0));
+    }
+
+    #[test]
+    fn late_night_window_follows_the_time_zone() {
+        // 20:00 to 22:00 UTC is 23:00 to 01:00 three hours east
+        let sessions = [session("Work", at(20, 0), at(22, 0))];
+        let east = FixedOffset::east_opt(3 * 3600).unwrap();
+
+        assert_eq!(late_night().late_seconds(&Utc, &sessions), 0);
+        assert_eq!(late_night().late_seconds(&east, &sessions), 2 * HOUR);
+    }
+
+    #[test]
+    fn late_night_window_without_wrapping() {
+        let model = LateNightModel {
+            start_hour: 1,
+            end_hour: 4,
+            ..late_night()
+        };
+        let sessions = [session("Work", at(0, 30), at(4, 30))];
+
+        assert_eq!(model.late_seconds(&Utc, &sessions), 3 * HOUR);
+    }
+}
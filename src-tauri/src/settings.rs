@@ -0,0 +1,375 @@
+// --------------------------------------------------------------------------
+// User settings kept in `settings.toml` in the config directory: how often
+// the tracker polls and flushes, the HUD's size and margins, and how much
+// history and how many backups are kept. Missing keys take their defaults,
+// so a hand-edited file only needs the values that differ; unknown keys are
+// ignored, so a file written by a newer build still loads.
+//
+// `update_settings` validates and saves a new set, makes it live and emits
+// `settings-changed` with it. The tracker and the daily jobs read the live
+// set on every pass, the HUD repositions itself on the event. Settings that
+// change what the stored data means (day start, scoring) stay in the
+// database, see `days` and `scoring`; changing them emits the same event, so
+// the frontend has one place to listen.
+// --------------------------------------------------------------------------
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, State};
+
+use crate::backup::BackupPolicy;
+use crate::db::RetentionPolicy;
+use crate::error::DeskflowError;
+use crate::tracker::TrackerConfig;
+
+/// Emitted with the current `Settings` whenever they or a setting kept in
+/// the database change.
+pub const CHANGED_EVENT: &str = "settings-changed";
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct Settings {
+    pub tracker: TrackerSettings,
+    pub hud: HudSettings,
+    pub history: HistorySettings,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct TrackerSettings {
+    /// Pause between two looks at the desktop.
+    pub poll_interval_ms: u64,
+    /// Seconds after which a window that stays open is written out.
+    pub flush_interval_secs: u64,
+    /// Seconds without input after which the time counts as idle.
+    pub idle_threshold_secs: u64,
+}
+
+impl Default for TrackerSettings {
+    fn default() -> Self {
+        let config = TrackerConfig::default();
+        Self {
+            poll_interval_ms: config.poll_interval.as_millis() as u64,
+            flush_interval_secs: config.flush_interval,
+            idle_threshold_secs: config.idle_threshold,
+        }
+    }
+}
+
+/// Size of the HUD in physical pixels and its distance from the bottom-right
+/// corner of the monitor.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct HudSettings {
+    pub width: f64,
+    pub height: f64,
+    pub margin_x: f64,
+    pub margin_y: f64,
+}
+
+impl Default for HudSettings {
+    fn default() -> Self {
+        Self {
+            width: 500.0,
+            height: 400.0,
+            margin_x: 24.0,
+            margin_y: 84.0,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct HistorySettings {
+    /// Days of raw sessions kept before they are rolled up.
+    pub raw_days: u32,
+    /// Merge back-to-back rows of the same window once a day is over.
+    pub compact: bool,
+    /// Daily backups kept; 0 turns them off.
+    pub backups_kept: usize,
+}
+
+impl Default for HistorySettings {
+    fn default() -> Self {
+        let retention = RetentionPolicy::default();
+        Self {
+            raw_days: retention.raw_days,
+            compact: retention.compact,
+            backups_kept: BackupPolicy::default().keep,
+        }
+    }
+}
+
+impl Settings {
+    pub fn validate(&self) -> Result<(), DeskflowError> {
+        let tracker = &self.tracker;
+        if !(100..=60_000).contains(&tracker.poll_interval_ms) {
+            return Err(DeskflowError::config("poll interval must be between 100 ms and 60 s"));
+        }
+        if tracker.flush_interval_secs == 0 || tracker.flush_interval_secs.saturating_mul(1000) < tracker.poll_interval_ms {
+            return Err(DeskflowError::config("flush interval must be at least one poll interval"));
+        }
+        if tracker.idle_threshold_secs < 10 {
+            return Err(DeskflowError::config("idle threshold must be at least 10 seconds"));
+        }
+
+        let hud = &self.hud;
+        if ![hud.width, hud.height].iter().all(|size| (100.0..=8000.0).contains(size)) {
+            return Err(DeskflowError::config("HUD width and height must be between 100 and 8000 pixels"));
+        }
+        if ![hud.margin_x, hud.margin_y].iter().all(|margin| (0.0..=8000.0).contains(margin)) {
+            return Err(DeskflowError::config("HUD margins must be between 0 and 8000 pixels"));
+        }
+
+        if self.history.raw_days == 0 {
+            return Err(DeskflowError::config("raw sessions must be kept for at least a day"));
+        }
+        Ok(())
+    }
+
+    pub fn tracker_config(&self) -> TrackerConfig {
+        TrackerConfig {
+            poll_interval: Duration::from_millis(self.tracker.poll_interval_ms),
+            flush_interval: self.tracker.flush_interval_secs,
+            idle_threshold: self.tracker.idle_threshold_secs,
+        }
+    }
+
+    pub fn retention_policy(&self) -> RetentionPolicy {
+        RetentionPolicy {
+            raw_days: self.history.raw_days,
+            compact: self.history.compact,
+        }
+    }
+
+    pub fn backup_policy(&self) -> BackupPolicy {
+        BackupPolicy {
+            keep: self.history.backups_kept,
+        }
+    }
+}
+
+
+// --------------- settings.toml --------------- //
+
+/// Parse and validate the contents of a `settings.toml`.
+pub fn from_toml(contents: &str) -> Result<Settings, DeskflowError> {
+    let settings: Settings = toml::from_str(contents).map_err(|e| DeskflowError::config(e.to_string()))?;
+    settings.validate()?;
+    Ok(settings)
+}
+
+pub fn to_toml(settings: &Settings) -> Result<String, DeskflowError> {
+    toml::to_string_pretty(settings).map_err(|e| DeskflowError::config(e.to_string()))
+}
+
+pub fn read_settings_file(path: &Path) -> Result<Settings, DeskflowError> {
+    let contents = fs::read_to_string(path).map_err(|e| DeskflowError::io_at(path, e))?;
+    from_toml(&contents).map_err(|e| e.context(path.display()))
+}
+
+/// Write `settings` to `path` through a temporary file, so a crash never
+/// leaves half a file behind.
+pub fn write_settings_file(path: &Path, settings: &Settings) -> Result<(), DeskflowError> {
+    let contents = to_toml(settings)?;
+
+    let mut partial = path.as_os_str().to_os_string();
+    partial.push(".partial");
+    let partial = PathBuf::from(partial);
+
+    fs::write(&partial, contents).map_err(|e| DeskflowError::io_at(&partial, e))?;
+    fs::rename(&partial, path).map_err(|e| DeskflowError::io_at(path, e))
+}
+
+
+// --------------- Live settings --------------- //
+
+/// The settings in effect, shared with the tracker and the daily jobs.
+#[derive(Clone)]
+pub struct ActiveSettings {
+    current: Arc<RwLock<Settings>>,
+    path: PathBuf,
+}
+
+impl ActiveSettings {
+    /// Read `path`, writing the defaults there on first run. A broken file
+    /// is left alone for the user to fix and the defaults are used.
+    pub fn load(path: &Path) -> Self {
+        let settings = if path.exists() {
+            read_settings_file(path).unwrap_or_else(|e| {
+                eprintln!("Using default settings: {}", e);
+                Settings::default()
+            })
+        } else {
+            let settings = Settings::default();
+            if let Err(e) = write_settings_file(path, &settings) {
+                eprintln!("Failed to write default settings: {}", e);
+            }
+            settings
+        };
+
+        Self {
+            current: Arc::new(RwLock::new(settings)),
+            path: path.to_path_buf(),
+        }
+    }
+
+    pub fn current(&self) -> Settings {
+        self.current.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Validate and save `settings`, then make them live.
+    pub fn replace(&self, settings: Settings) -> Result<(), DeskflowError> {
+        settings.validate()?;
+        write_settings_file(&self.path, &settings)?;
+        *self.current.write().unwrap_or_else(|e| e.into_inner()) = settings;
+        Ok(())
+    }
+}
+
+
+/// Tell every window that settings changed, with the ones now in effect.
+pub fn announce(app: &AppHandle, active: &ActiveSettings) {
+    if let Err(e) = app.emit(CHANGED_EVENT, active.current()) {
+        eprintln!("Failed to announce new settings: {}", e);
+    }
+}
+
+
+// --------------- Commands --------------- //
+
+#[tauri::command]
+pub fn get_settings(active: State<'_, ActiveSettings>) -> Settings {
+    active.current()
+}
+
+/// Replace the settings with `settings` and tell every window about it.
+#[tauri::command]
+pub fn update_settings(
+    app: AppHandle,
+    active: State<'_, ActiveSettings>,
+    settings: Settings,
+) -> Result<Settings, DeskflowError> {
+    active.replace(settings.clone())?;
+    announce(&app, &active);
+    Ok(settings)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn config_error(contents: &str) -> String {
+        match from_toml(contents) {
+            Err(err @ DeskflowError::Config(_)) => err.to_string(),
+            other => panic!("expected a config error for {:?}, got {:?}", contents, other),
+        }
+    }
+
+    #[test]
+    fn defaults_are_valid_and_round_trip() {
+        let defaults = Settings::default();
+        defaults.validate().unwrap();
+        assert_eq!(from_toml(&to_toml(&defaults).unwrap()).unwrap(), defaults);
+    }
+
+    #[test]
+    fn missing_keys_take_their_defaults() {
+        assert_eq!(from_toml("").unwrap(), Settings::default());
+
+        let settings =
+            from_toml("[tracker]\npoll_interval_ms = 2000\n\n[history]\ncompact = false\n").unwrap();
+        assert_eq!(settings.tracker.poll_interval_ms, 2000);
+        assert_eq!(
+            settings.tracker.idle_threshold_secs,
+            TrackerSettings::default().idle_threshold_secs
+        );
+        assert!(!settings.history.compact);
+        assert_eq!(settings.history.raw_days, HistorySettings::default().raw_days);
+        assert_eq!(settings.hud, HudSettings::default());
+    }
+
+    #[test]
+    fn out_of_range_values_are_rejected() {
+        for (contents, expected) in [
+            ("[tracker]\npoll_interval_ms = 50", "poll interval"),
+            ("[tracker]\npoll_interval_ms = 120000", "poll interval"),
+            ("[tracker]\nflush_interval_secs = 0", "flush interval"),
+            (
+                "[tracker]\npoll_interval_ms = 10000\nflush_interval_secs = 5",
+                "flush interval",
+            ),
+            ("[tracker]\nidle_threshold_secs = 5", "idle threshold"),
+            ("[hud]\nwidth = 50.0", "width and height"),
+            ("[hud]\nheight = 9000.0", "width and height"),
+            ("[hud]\nmargin_x = -1.0", "margins"),
+            ("[history]\nraw_days = 0", "at least a day"),
+        ] {
+            let err = config_error(contents);
+            assert!(err.contains(expected), "{:?}: {}", contents, err);
+        }
+    }
+
+    #[test]
+    fn malformed_files_are_rejected() {
+        config_error("[tracker]\npoll_interval_ms = \"fast\"");
+        config_error("[tracker]\npoll_interval_ms = -5");
+        config_error("[tracker");
+    }
+
+    #[test]
+    fn unknown_keys_are_ignored() {
+        // A file written by a newer build still loads
+        let settings =
+            from_toml("theme = \"dark\"\n\n[hud]\nwidth = 600.0\nopacity = 0.5\n\n[sync]\nenabled = true\n")
+                .unwrap();
+        assert_eq!(settings.hud.width, 600.0);
+    }
+
+    #[test]
+    fn first_run_writes_the_defaults() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("settings.toml");
+
+        let active = ActiveSettings::load(&path);
+
+        assert_eq!(active.current(), Settings::default());
+        assert_eq!(read_settings_file(&path).unwrap(), Settings::default());
+    }
+
+    #[test]
+    fn a_broken_file_is_left_alone() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("settings.toml");
+        fs::write(&path, "[history]\nraw_days = 0\n").unwrap();
+
+        let active = ActiveSettings::load(&path);
+
+        assert_eq!(active.current(), Settings::default());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "[history]\nraw_days = 0\n");
+    }
+
+    #[test]
+    fn invalid_replacements_change_nothing() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("settings.toml");
+        let active = ActiveSettings::load(&path);
+
+        let mut invalid = Settings::default();
+        invalid.tracker.idle_threshold_secs = 1;
+        assert!(active.replace(invalid).is_err());
+        assert_eq!(read_settings_file(&path).unwrap(), Settings::default());
+
+        let mut valid = Settings::default();
+        valid.history.backups_kept = 0;
+        active.replace(valid.clone()).unwrap();
+        assert_eq!(active.current(), valid);
+        assert_eq!(read_settings_file(&path).unwrap(), valid);
+    }
+}
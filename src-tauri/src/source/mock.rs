@@ -0,0 +1,68 @@
+// --------------------------------------------------------------------------
+// Scripted window source for tests. Replays a fixed sequence of desktop
+// states so the tracker can be driven without a real window system.
+// --------------------------------------------------------------------------
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use super::{WindowInfo, WindowSource};
+
+/// What the desktop looks like during one tracker tick.
+#[derive(Clone, Debug, Default)]
+pub struct Frame {
+    pub active: Option<WindowInfo>,
+    pub visible: Vec<WindowInfo>,
+    pub processes: Vec<String>,
+}
+
+/// Every method reports the current frame, in any order and as often as
+/// asked; `advance` moves on to the next one. Once the script runs out the
+/// final frame is repeated.
+#[derive(Default)]
+pub struct MockWindowSource {
+    state: Mutex<MockState>,
+}
+
+#[derive(Default)]
+struct MockState {
+    pending: VecDeque<Frame>,
+    current: Frame,
+}
+
+impl MockWindowSource {
+    /// A source showing the first of `frames`.
+    pub fn new(frames: impl IntoIterator<Item = Frame>) -> Self {
+        let mut pending: VecDeque<Frame> = frames.into_iter().collect();
+        let current = pending.pop_front().unwrap_or_default();
+        Self {
+            state: Mutex::new(MockState { pending, current }),
+        }
+    }
+
+    /// Move on to the next frame, if there is one.
+    pub fn advance(&self) {
+        let mut state = self.lock();
+        if let Some(next) = state.pending.pop_front() {
+            state.current = next;
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, MockState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl WindowSource for MockWindowSource {
+    fn active_window(&self) -> Option<WindowInfo> {
+        self.lock().current.active.clone()
+    }
+
+    fn visible_windows(&self) -> Vec<WindowInfo> {
+        self.lock().current.visible.clone()
+    }
+
+    fn running_processes(&self) -> Vec<String> {
+        self.lock().current.processes.clone()
+    }
+}
@@ -0,0 +1,82 @@
+// --------------------------------------------------------------------------
+// Where the tracker gets its view of the desktop from. Each platform backend
+// implements `WindowSource`; the tracking loop and the Tauri commands only
+// ever talk to the trait object picked at startup.
+// --------------------------------------------------------------------------
+
+use std::sync::Arc;
+
+#[cfg(test)]
+pub mod mock;
+#[cfg(target_os = "windows")]
+pub mod windows;
+#[cfg(target_os = "linux")]
+pub mod x11;
+
+#[cfg(test)]
+pub use mock::MockWindowSource;
+
+/// A top-level window as the platform reports it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WindowInfo {
+    /// Platform handle (HWND, X window id); tells apart windows that share
+    /// a title and process.
+    pub id: u64,
+    pub title: String,
+    pub process: String,
+}
+
+impl WindowInfo {
+    /// `(title, process)`, what sessions are recorded under.
+    pub fn into_key(self) -> (String, String) {
+        (self.title, self.process)
+    }
+}
+
+/// Each method asks the desktop afresh; they may be called in any order.
+/// Both window methods name processes the same way, so the active window is
+/// also found among the visible ones by its `id`.
+pub trait WindowSource: Send + Sync {
+    /// The window that currently has focus.
+    fn active_window(&self) -> Option<WindowInfo>;
+
+    /// Every window the user can currently see.
+    fn visible_windows(&self) -> Vec<WindowInfo>;
+
+    /// Executable names of the running processes.
+    fn running_processes(&self) -> Vec<String>;
+}
+
+/// Used where no backend exists; the desktop always looks empty.
+#[cfg(not(any(target_os = "windows", target_os = "linux")))]
+pub struct NoWindowSource;
+
+#[cfg(not(any(target_os = "windows", target_os = "linux")))]
+impl WindowSource for NoWindowSource {
+    fn active_window(&self) -> Option<WindowInfo> {
+        None
+    }
+
+    fn visible_windows(&self) -> Vec<WindowInfo> {
+        Vec::new()
+    }
+
+    fn running_processes(&self) -> Vec<String> {
+        Vec::new()
+    }
+}
+
+#[cfg(target_os = "windows")]
+pub fn default_source() -> Arc<dyn WindowSource> {
+    Arc::new(windows::WindowsSource)
+}
+
+#[cfg(target_os = "linux")]
+pub fn default_source() -> Arc<dyn WindowSource> {
+    Arc::new(x11::X11Source::new(None))
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "linux")))]
+pub fn default_source() -> Arc<dyn WindowSource> {
+    Arc::new(NoWindowSource)
+}
@@ -0,0 +1,178 @@
+// --------------------------------------------------------------------------
+// Win32 backend: foreground window, EnumWindows for everything visible, and a
+// ToolHelp snapshot for the process list.
+// --------------------------------------------------------------------------
+
+use windows::{
+    core::PWSTR,
+    Win32::{
+        Foundation::{CloseHandle, BOOL, HANDLE, HWND, LPARAM, MAX_PATH},
+        System::{
+            Diagnostics::ToolHelp::{
+                CreateToolhelp32Snapshot, Process32FirstW, Process32NextW, PROCESSENTRY32W,
+                TH32CS_SNAPPROCESS,
+            },
+            Threading::{
+                OpenProcess, QueryFullProcessImageNameW, PROCESS_NAME_FORMAT,
+                PROCESS_QUERY_LIMITED_INFORMATION,
+            },
+        },
+        UI::WindowsAndMessaging::*,
+    },
+};
+
+use super::{WindowInfo, WindowSource};
+
+pub struct WindowsSource;
+
+impl WindowSource for WindowsSource {
+    fn active_window(&self) -> Option<WindowInfo> {
+        get_active_window_info()
+    }
+
+    fn visible_windows(&self) -> Vec<WindowInfo> {
+        get_all_visible_windows()
+    }
+
+    fn running_processes(&self) -> Vec<String> {
+        get_running_processes()
+    }
+}
+
+fn get_active_window_info() -> Option<WindowInfo> {
+    unsafe {
+        let hwnd = GetForegroundWindow();
+        if hwnd.0 == 0 {
+            return None;
+        }
+
+        Some(window_info(hwnd))
+    }
+}
+
+fn get_all_visible_windows() -> Vec<WindowInfo> {
+    let mut windows_info: Vec<WindowInfo> = Vec::new();
+
+    unsafe extern "system" fn enum_window_proc(hwnd: HWND, lparam: LPARAM) -> BOOL {
+        let windows_info = &mut *(lparam.0 as *mut Vec<WindowInfo>);
+
+        // Skip invisible or empty title windows
+        if !IsWindowVisible(hwnd).as_bool() || GetWindowTextLengthW(hwnd) == 0 {
+            return true.into();
+        }
+
+        let info = window_info(hwnd);
+
+        // Skip system/UI windows
+        let skip_titles = ["Program Manager", "Settings", "Windows Input Experience"];
+        if skip_titles.iter().any(|t| info.title.contains(t)) {
+            return true.into();
+        }
+
+        // Filter system/UWP background processes
+        let skip_exe = [
+            "SystemSettings.exe", "StartMenuExperienceHost.exe",
+            "ShellExperienceHost.exe", "ApplicationFrameHost.exe",
+            "TextInputHost.exe", "SearchApp.exe"
+        ];
+
+        if skip_exe.iter().any(|p| info.process.eq_ignore_ascii_case(p)) {
+            return true.into();
+        }
+
+        windows_info.push(info);
+        true.into()
+    }
+
+    unsafe {
+        let _ = EnumWindows(Some(enum_window_proc), LPARAM(&mut windows_info as *mut _ as isize));
+    }
+
+    windows_info
+}
+
+/// Handle, title and executable name of `hwnd`. The foreground window and
+/// the enumeration both go through here, so they always agree on a name.
+unsafe fn window_info(hwnd: HWND) -> WindowInfo {
+    let mut title = [0u16; 512];
+    let len = GetWindowTextW(hwnd, &mut title);
+    let title = String::from_utf16_lossy(&title[..len as usize]);
+
+    let mut pid = 0;
+    GetWindowThreadProcessId(hwnd, Some(&mut pid));
+
+    WindowInfo {
+        id: hwnd.0 as u64,
+        title,
+        process: process_name(pid),
+    }
+}
+
+/// Executable name of `pid`. Limited query access is all
+/// QueryFullProcessImageNameW needs, and is granted for UWP and elevated
+/// processes too, where reading the process memory is not.
+unsafe fn process_name(pid: u32) -> String {
+    let Ok(process) = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, pid) else {
+        return "<access denied>".to_string();
+    };
+
+    let mut path = [0u16; MAX_PATH as usize];
+    let mut size = path.len() as u32;
+    let queried = QueryFullProcessImageNameW(process, PROCESS_NAME_FORMAT(0), PWSTR(path.as_mut_ptr()), &mut size);
+    let _ = CloseHandle(process);
+
+    if queried.is_err() {
+        return "<unknown>".to_string();
+    }
+    String::from_utf16_lossy(&path[..size as usize])
+        .rsplit('\\')
+        .next()
+        .unwrap_or("<unknown>")
+        .to_string()
+}
+
+fn get_running_processes() -> Vec<String> {
+    let mut processes = Vec::new();
+
+    unsafe {
+        let Ok(snapshot): Result<HANDLE, _> = CreateToolhelp32Snapshot(TH32CS_SNAPPROCESS, 0) else {
+            return processes; // failed to get snapshot
+        };
+
+        let mut entry = PROCESSENTRY32W {
+            dwSize: std::mem::size_of::<PROCESSENTRY32W>() as u32,
+            ..Default::default()
+        };
+
+        if Process32FirstW(snapshot, &mut entry).is_ok() {
+            loop {
+                let len = entry.szExeFile.iter().position(|&c| c == 0).unwrap_or(entry.szExeFile.len());
+                let name = String::from_utf16_lossy(&entry.szExeFile[..len]);
+
+                // Optional: Filter out system processes
+                let skip = [
+                    "svchost.exe",
+                    "System Idle Process",
+                    "System",
+                    "winlogon.exe",
+                    "csrss.exe",
+                    "smss.exe",
+                    "Registry",
+                    "Idle",
+                ];
+
+                if !skip.iter().any(|&s| s.eq_ignore_ascii_case(&name)) {
+                    processes.push(name);
+                }
+
+                // Process32NextW fails with ERROR_NO_MORE_FILES once the
+                // snapshot is exhausted
+                if Process32NextW(snapshot, &mut entry).is_err() {
+                    break;
+                }
+            }
+        }
+    }
+
+    processes
+}
@@ -0,0 +1,301 @@
+// --------------------------------------------------------------------------
+// X11 backend for window tracking on Linux. Everything goes through the EWMH
+// properties a compliant window manager keeps on the root window and on each
+// client, so it works the same against a real desktop or a bare Xvfb display.
+// --------------------------------------------------------------------------
+
+use std::fs;
+use std::sync::Mutex;
+
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::{Atom, AtomEnum, ConnectionExt, Window};
+use x11rb::rust_connection::RustConnection;
+
+use super::{WindowInfo, WindowSource};
+
+x11rb::atom_manager! {
+    Atoms: AtomsCookie {
+        _NET_ACTIVE_WINDOW,
+        _NET_CLIENT_LIST,
+        _NET_WM_NAME,
+        _NET_WM_PID,
+        _NET_WM_STATE,
+        _NET_WM_STATE_HIDDEN,
+        UTF8_STRING,
+    }
+}
+
+pub struct X11Backend {
+    conn: RustConnection,
+    root: Window,
+    atoms: Atoms,
+}
+
+impl X11Backend {
+    /// Connect to `display`, or to `$DISPLAY` when `None`.
+    pub fn connect(display: Option<&str>) -> Option<Self> {
+        let (conn, screen_num) = x11rb::connect(display).ok()?;
+        let root = conn.setup().roots.get(screen_num)?.root;
+        let atoms = Atoms::new(&conn).ok()?.reply().ok()?;
+        Some(Self { conn, root, atoms })
+    }
+
+    /// The window the window manager reports as active.
+    pub fn active_window_info(&self) -> Option<WindowInfo> {
+        let window = self
+            .window_list(self.root, self.atoms._NET_ACTIVE_WINDOW)
+            .into_iter()
+            .next()
+            .filter(|&w| w != x11rb::NONE)?;
+
+        Some(self.window_info(window))
+    }
+
+    /// Every managed client that is not minimised and has a title.
+    pub fn visible_windows(&self) -> Vec<WindowInfo> {
+        self.window_list(self.root, self.atoms._NET_CLIENT_LIST)
+            .into_iter()
+            .filter(|&w| !self.is_hidden(w))
+            .map(|w| self.window_info(w))
+            .filter(|info| !info.title.is_empty())
+            .collect()
+    }
+
+    fn window_info(&self, window: Window) -> WindowInfo {
+        let title = self.window_title(window).unwrap_or_default();
+        let process = self
+            .window_pid(window)
+            .and_then(process_name)
+            .unwrap_or_else(|| "<unknown>".to_string());
+        WindowInfo {
+            id: window.into(),
+            title,
+            process,
+        }
+    }
+
+    fn window_title(&self, window: Window) -> Option<String> {
+        // Prefer the UTF-8 EWMH name, fall back to the legacy ICCCM WM_NAME
+        self.string_property(window, self.atoms._NET_WM_NAME, self.atoms.UTF8_STRING)
+            .or_else(|| self.string_property(window, AtomEnum::WM_NAME.into(), AtomEnum::STRING.into()))
+    }
+
+    fn window_pid(&self, window: Window) -> Option<u32> {
+        let reply = self
+            .conn
+            .get_property(false, window, self.atoms._NET_WM_PID, AtomEnum::CARDINAL, 0, 1)
+            .ok()?
+            .reply()
+            .ok()?;
+        let pid = reply.value32()?.next();
+        pid
+    }
+
+    fn is_hidden(&self, window: Window) -> bool {
+        self.atom_list(window, self.atoms._NET_WM_STATE)
+            .contains(&self.atoms._NET_WM_STATE_HIDDEN)
+    }
+
+    fn window_list(&self, window: Window, property: Atom) -> Vec<Window> {
+        self.u32_list(window, property, AtomEnum::WINDOW.into())
+    }
+
+    fn atom_list(&self, window: Window, property: Atom) -> Vec<Atom> {
+        self.u32_list(window, property, AtomEnum::ATOM.into())
+    }
+
+    fn u32_list(&self, window: Window, property: Atom, kind: Atom) -> Vec<u32> {
+        let reply = self
+            .conn
+            .get_property(false, window, property, kind, 0, u32::MAX)
+            .ok()
+            .and_then(|cookie| cookie.reply().ok());
+
+        let values = match reply.as_ref().and_then(|r| r.value32()) {
+            Some(values) => values.collect(),
+            None => Vec::new(),
+        };
+        values
+    }
+
+    fn string_property(&self, window: Window, property: Atom, kind: Atom) -> Option<String> {
+        let reply = self
+            .conn
+            .get_property(false, window, property, kind, 0, u32::MAX)
+            .ok()?
+            .reply()
+            .ok()?;
+
+        if reply.value.is_empty() {
+            return None;
+        }
+        Some(String::from_utf8_lossy(&reply.value).into_owned())
+    }
+}
+
+/// Executable name of `pid`, as the kernel reports it in `/proc/<pid>/comm`.
+fn process_name(pid: u32) -> Option<String> {
+    let comm = fs::read_to_string(format!("/proc/{pid}/comm")).ok()?;
+    let name = comm.trim_end();
+    if name.is_empty() {
+        None
+    } else {
+        Some(name.to_string())
+    }
+}
+
+/// Names of all user-space processes. Kernel threads have an empty cmdline
+/// and are skipped, the same way the Windows version skips system processes.
+fn running_processes() -> Vec<String> {
+    let Ok(entries) = fs::read_dir("/proc") else {
+        return Vec::new();
+    };
+
+    entries
+        .flatten()
+        .filter_map(|entry| entry.file_name().to_str()?.parse::<u32>().ok())
+        .filter(|pid| {
+            fs::read(format!("/proc/{pid}/cmdline"))
+                .map(|cmdline| !cmdline.is_empty())
+                .unwrap_or(false)
+        })
+        .filter_map(process_name)
+        .collect()
+}
+
+/// `WindowSource` over a lazily opened X connection. The connection is
+/// re-opened if the X server went away (e.g. the session was restarted
+/// under us).
+pub struct X11Source {
+    display: Option<String>,
+    backend: Mutex<Option<X11Backend>>,
+}
+
+impl X11Source {
+    /// Track `display`, or `$DISPLAY` when `None`.
+    pub fn new(display: Option<String>) -> Self {
+        Self {
+            display,
+            backend: Mutex::new(None),
+        }
+    }
+
+    fn with_backend<T>(&self, f: impl FnOnce(&X11Backend) -> T) -> Option<T> {
+        let mut backend = self.backend.lock().unwrap_or_else(|e| e.into_inner());
+
+        if backend.as_ref().is_some_and(|b| b.conn.flush().is_err()) {
+            *backend = None;
+        }
+        if backend.is_none() {
+            *backend = X11Backend::connect(self.display.as_deref());
+        }
+
+        backend.as_ref().map(f)
+    }
+}
+
+impl WindowSource for X11Source {
+    fn active_window(&self) -> Option<WindowInfo> {
+        self.with_backend(|b| b.active_window_info()).flatten()
+    }
+
+    fn visible_windows(&self) -> Vec<WindowInfo> {
+        self.with_backend(|b| b.visible_windows()).unwrap_or_default()
+    }
+
+    fn running_processes(&self) -> Vec<String> {
+        running_processes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use x11rb::protocol::xproto::{CreateWindowAux, PropMode, WindowClass};
+    use x11rb::wrapper::ConnectionExt as _;
+
+    /// Needs an X server without a window manager, which is what a bare
+    /// Xvfb is; the test plays the window manager's part itself:
+    ///
+    ///     Xvfb :99 &
+    ///     DESKFLOW_TEST_DISPLAY=:99 cargo test x11 -- --ignored
+    #[test]
+    #[ignore]
+    fn reads_active_window_from_xvfb() {
+        let display = std::env::var("DESKFLOW_TEST_DISPLAY").unwrap_or_else(|_| ":99".to_string());
+        let backend = X11Backend::connect(Some(&display)).expect("no X server on DESKFLOW_TEST_DISPLAY");
+
+        // A client window with an EWMH title and our own pid
+        let (conn, screen_num) = x11rb::connect(Some(&display)).unwrap();
+        let root = conn.setup().roots[screen_num].root;
+        let atoms = Atoms::new(&conn).unwrap().reply().unwrap();
+        let window = conn.generate_id().unwrap();
+        conn.create_window(
+            0,
+            window,
+            root,
+            0,
+            0,
+            100,
+            100,
+            0,
+            WindowClass::INPUT_OUTPUT,
+            0,
+            &CreateWindowAux::new(),
+        )
+        .unwrap();
+        let title = "Notes – ünïcode";
+        conn.change_property8(
+            PropMode::REPLACE,
+            window,
+            atoms._NET_WM_NAME,
+            atoms.UTF8_STRING,
+            title.as_bytes(),
+        )
+        .unwrap();
+        conn.change_property32(
+            PropMode::REPLACE,
+            window,
+            atoms._NET_WM_PID,
+            AtomEnum::CARDINAL,
+            &[std::process::id()],
+        )
+        .unwrap();
+
+        // What the window manager would announce on the root window
+        conn.change_property32(
+            PropMode::REPLACE,
+            root,
+            atoms._NET_CLIENT_LIST,
+            AtomEnum::WINDOW,
+            &[window],
+        )
+        .unwrap();
+        conn.change_property32(
+            PropMode::REPLACE,
+            root,
+            atoms._NET_ACTIVE_WINDOW,
+            AtomEnum::WINDOW,
+            &[window],
+        )
+        .unwrap();
+        conn.sync().unwrap();
+
+        let process = fs::read_to_string("/proc/self/comm")
+            .unwrap()
+            .trim_end()
+            .to_string();
+        let expected = WindowInfo {
+            id: window.into(),
+            title: title.to_string(),
+            process,
+        };
+        assert_eq!(backend.active_window_info(), Some(expected.clone()));
+        assert_eq!(backend.visible_windows(), vec![expected]);
+
+        conn.destroy_window(window).unwrap();
+        conn.delete_property(root, atoms._NET_CLIENT_LIST).unwrap();
+        conn.delete_property(root, atoms._NET_ACTIVE_WINDOW).unwrap();
+        conn.sync().unwrap();
+    }
+}
@@ -0,0 +1,276 @@
+// --------------------------------------------------------------------------
+// Day timeline. The tracker writes a window out every `flush_interval`
+// seconds, so an hour in one window is hundreds of rows that follow each
+// other exactly. Rows of the same window (app, title, category, rule and
+// focus) that start within `gap` seconds of the previous one's end are
+// merged into one span. Compaction does the same merge in the database for
+// days that are over, keeping the first row of each span, but only for rows
+// that follow each other exactly: stretching a row over a gap would add time
+// that was never tracked.
+// --------------------------------------------------------------------------
+
+use std::collections::HashMap;
+
+use chrono::{Duration, NaiveDate};
+use rusqlite::{Connection, Result};
+use serde::Serialize;
+use tauri::State;
+
+use crate::db::{get_state, set_state, Db};
+use crate::error::DeskflowError;
+use crate::reports::parse_date;
+
+/// Largest gap between two rows that still counts as the same span.
+pub const DEFAULT_GAP_SECONDS: u64 = 5;
+
+const LAST_COMPACTED_KEY: &str = "last_compacted_date";
+
+#[derive(Serialize, Clone, Debug)]
+pub struct Span {
+    /// `app_usage` rows the span was built from, oldest first.
+    #[serde(skip)]
+    pub ids: Vec<i64>,
+    pub app_name: String,
+    pub window_title: String,
+    pub category: String,
+    pub rule_id: Option<String>,
+    pub is_focused: bool,
+    pub start_time: u64,
+    pub end_time: u64,
+}
+
+type SpanKey = (String, String, String, Option<String>, bool);
+
+fn span_key(span: &Span) -> SpanKey {
+    (
+        span.app_name.clone(),
+        span.window_title.clone(),
+        span.category.clone(),
+        span.rule_id.clone(),
+        span.is_focused,
+    )
+}
+
+/// Merge `rows`, sorted by start time, into spans. Windows are visible at
+/// the same time, so a row is only compared with the latest span of its
+/// own window.
+pub fn merge_spans(rows: Vec<Span>, gap: u64) -> Vec<Span> {
+    let mut spans: Vec<Span> = Vec::new();
+    let mut latest: HashMap<SpanKey, usize> = HashMap::new();
+
+    for row in rows {
+        let key = span_key(&row);
+
+        if let Some(&index) = latest.get(&key) {
+            let span = &mut spans[index];
+            if row.start_time <= span.end_time + gap {
+                span.end_time = span.end_time.max(row.end_time);
+                span.ids.extend(row.ids);
+                continue;
+            }
+        }
+
+        latest.insert(key, spans.len());
+        spans.push(row);
+    }
+
+    spans
+}
+
+/// Every row of `date` as a one-row span, sorted by start time.
+fn load_rows(conn: &Connection, date: NaiveDate, focused_only: bool) -> Result<Vec<Span>> {
+    let mut stmt = conn.prepare_cached(
+        "SELECT id, app_name, window_title, category, rule_id, is_focused, start_time, end_time
+         FROM app_usage
+         WHERE date = ?1 AND (NOT ?2 OR is_focused)
+         ORDER BY start_time, id",
+    )?;
+
+    let rows = stmt.query_map((date.to_string(), focused_only), |row| {
+        Ok(Span {
+            ids: vec![row.get(0)?],
+            app_name: row.get(1)?,
+            window_title: row.get(2)?,
+            category: row.get(3)?,
+            rule_id: row.get(4)?,
+            is_focused: row.get(5)?,
+            start_time: row.get(6)?,
+            end_time: row.get(7)?,
+        })
+    })?;
+    rows.collect()
+}
+
+pub fn timeline(conn: &Connection, date: NaiveDate, gap: u64, focused_only: bool) -> Result<Vec<Span>> {
+    Ok(merge_spans(load_rows(conn, date, focused_only)?, gap))
+}
+
+
+// --------------- Compaction --------------- //
+
+/// Merge the back-to-back rows of every day from `from` to `to` in place.
+/// Returns the number of rows removed.
+pub fn compact(conn: &mut Connection, from: NaiveDate, to: NaiveDate) -> Result<usize> {
+    let tx = conn.transaction()?;
+    let mut removed = 0;
+
+    {
+        let mut extend = tx.prepare_cached("UPDATE app_usage SET end_time = ?1 WHERE id = ?2")?;
+        let mut delete = tx.prepare_cached("DELETE FROM app_usage WHERE id = ?1")?;
+
+        let mut day = from;
+        while day <= to {
+            for span in timeline(&tx, day, 0, false)? {
+                let Some((first, rest)) = span.ids.split_first() else {
+                    continue;
+                };
+                if rest.is_empty() {
+                    continue;
+                }
+
+                extend.execute((span.end_time, first))?;
+                for id in rest {
+                    removed += delete.execute([id])?;
+                }
+            }
+            day += Duration::days(1);
+        }
+    }
+
+    tx.commit()?;
+    Ok(removed)
+}
+
+/// Compact the days since the last compaction, up to and including
+/// yesterday, but nothing before `earliest`.
+pub fn compact_finished_days(conn: &mut Connection, earliest: NaiveDate, today: NaiveDate) -> Result<usize> {
+    let yesterday = today - Duration::days(1);
+
+    let from = get_state(conn, LAST_COMPACTED_KEY)?
+        .and_then(|date| NaiveDate::parse_from_str(&date, "%Y-%m-%d").ok())
+        .map_or(earliest, |last| (last + Duration::days(1)).max(earliest));
+    if from > yesterday {
+        return Ok(0);
+    }
+
+    let removed = compact(conn, from, yesterday)?;
+    set_state(conn, LAST_COMPACTED_KEY, &yesterday.to_string())?;
+    Ok(removed)
+}
+
+
+// --------------- Commands --------------- //
+
+#[tauri::command]
+pub fn get_timeline(
+    db: State<'_, Db>,
+    date: String,
+    gap_seconds: Option<u64>,
+    focused_only: Option<bool>,
+) -> Result<Vec<Span>, DeskflowError> {
+    let date = parse_date(&date)?;
+
+    Ok(timeline(
+        &db.lock(),
+        date,
+        gap_seconds.unwrap_or(DEFAULT_GAP_SECONDS),
+        focused_only.unwrap_or(false),
+    )?)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    const DAY: &str = "2024-05-06";
+
+    fn day() -> NaiveDate {
+        NaiveDate::parse_from_str(DAY, "%Y-%m-%d").unwrap()
+    }
+
+    fn database(rows: &[(u64, u64)]) -> Connection {
+        let mut conn = Connection::open_in_memory().unwrap();
+        crate::migrations::migrate(&mut conn, Path::new(":memory:")).unwrap();
+        for (start, end) in rows {
+            conn.execute(
+                "INSERT INTO app_usage (app_name, window_title, category, start_time, end_time, date, is_focused)
+                 VALUES ('code', 'main.rs', 'Work', ?1, ?2, ?3, 1)",
+                (start, end, DAY),
+            )
+            .unwrap();
+        }
+        conn
+    }
+
+    fn stored(conn: &Connection) -> Vec<(u64, u64)> {
+        conn.prepare("SELECT start_time, end_time FROM app_usage ORDER BY start_time")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn compaction_only_merges_back_to_back_rows() {
+        let mut conn = database(&[(0, 5), (5, 10), (10, 15), (18, 23), (23, 28)]);
+
+        assert_eq!(compact(&mut conn, day(), day()).unwrap(), 3);
+
+        // The three seconds between 15 and 18 were never tracked
+        assert_eq!(stored(&conn), vec![(0, 15), (18, 28)]);
+    }
+
+    #[test]
+    fn timeline_bridges_gaps_without_touching_rows() {
+        let conn = database(&[(0, 5), (5, 10), (13, 18)]);
+
+        let spans = timeline(&conn, day(), DEFAULT_GAP_SECONDS, false).unwrap();
+
+        let bounds: Vec<_> = spans.iter().map(|s| (s.start_time, s.end_time)).collect();
+        assert_eq!(bounds, vec![(0, 18)]);
+        assert_eq!(stored(&conn), vec![(0, 5), (5, 10), (13, 18)]);
+    }
+
+    #[test]
+    fn compaction_keeps_the_score() {
+        use crate::scoring::{compute_score, ModelKind, ScoringConfig};
+
+        let base = 1_700_000_000;
+        let rows = [
+            ("Work", 0, 600),
+            ("Work", 600, 1200),
+            ("Work", 1200, 1800),
+            ("Browsing", 1830, 1900),
+            ("Work", 1930, 2500),
+            ("Work", 2500, 4000),
+        ];
+        let database = || {
+            let conn = database(&[]);
+            for (category, start, end) in rows {
+                conn.execute(
+                    "INSERT INTO app_usage (app_name, window_title, category, start_time, end_time, date, is_focused)
+                     VALUES ('app', 'title', ?1, ?2, ?3, ?4, 1)",
+                    (category, base + start, base + end, DAY),
+                )
+                .unwrap();
+            }
+            conn
+        };
+        let (raw, mut compacted) = (database(), database());
+        assert_eq!(compact(&mut compacted, day(), day()).unwrap(), 3);
+
+        for kind in [ModelKind::Ratio, ModelKind::Weighted, ModelKind::DeepWork, ModelKind::LateNight] {
+            let config = ScoringConfig {
+                model: kind,
+                ..ScoringConfig::default()
+            };
+            let model = config.model();
+            let expected = compute_score(&raw, model.as_ref(), day()).unwrap();
+            assert!(expected.is_some());
+            assert_eq!(compute_score(&compacted, model.as_ref(), day()).unwrap(), expected, "{kind:?}");
+        }
+    }
+}
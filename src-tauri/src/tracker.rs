@@ -0,0 +1,604 @@
+// --------------------------------------------------------------------------
+// Session tracking. Every poll the tracker is handed the windows that are
+// visible right now and turns them into `AppSession`s: one when a window
+// disappears, and one every `flush_interval` for windows that stay open so a
+// crash never loses more than a few seconds of data. The focused window is
+// tracked alongside; gaining or losing focus starts a new session so focused
+// and background time never share a row. Once nobody has touched the machine
+// for `idle_threshold` seconds, all windows are closed and the time is
+// recorded under the `Idle` pseudo-category until input resumes. A long
+// gap between two ticks (the machine slept) or a clock that steps backwards
+// closes everything at the last tick it was seen, so the gap counts as
+// nothing.
+//
+// When the database refuses a write (locked, disk full, ...) the sessions
+// are held back and the write is retried with exponential backoff, while
+// tracking carries on.
+// --------------------------------------------------------------------------
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::db::{save_sessions, AppSession, Db};
+use crate::idle::IdleDetector;
+use crate::rules::{ActiveRules, Categorizer};
+use crate::settings::ActiveSettings;
+use crate::source::{WindowInfo, WindowSource};
+
+/// Category (and app name) of the sessions that record time away.
+pub const IDLE_CATEGORY: &str = "Idle";
+
+/// Longest wait between two attempts to store held-back sessions, seconds.
+const MAX_RETRY_DELAY: u64 = 60;
+
+/// Held-back sessions beyond this many are dropped, oldest first, so a
+/// database that stays unwritable doesn't eat memory.
+const MAX_PENDING_SESSIONS: usize = 10_000;
+
+/// A tick arriving more than this many poll intervals after the previous
+/// one means the machine was suspended in between.
+const SUSPEND_POLLS: u32 = 10;
+
+/// Source of "now" for the tracking loop, in Unix seconds.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> u64;
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct TrackerConfig {
+    /// How long the loop sleeps between two ticks.
+    pub poll_interval: Duration,
+    /// Seconds after which a still-open window is written out and restarted.
+    pub flush_interval: u64,
+    /// Seconds without keyboard or mouse input after which the user is idle.
+    pub idle_threshold: u64,
+}
+
+impl Default for TrackerConfig {
+    fn default() -> Self {
+        Self {
+            poll_interval: Duration::from_secs(1),
+            flush_interval: 5,
+            idle_threshold: 5 * 60,
+        }
+    }
+}
+
+/// Everything the tracker learns about the desktop in one poll.
+#[derive(Clone, Debug, Default)]
+pub struct Snapshot {
+    /// Every visible window.
+    pub windows: Vec<WindowInfo>,
+    /// Handle of the focused window, if any.
+    pub active: Option<u64>,
+    /// Seconds since the last input, if the idle detector could tell.
+    pub idle_seconds: Option<u64>,
+}
+
+impl Snapshot {
+    /// Ask `source` about the desktop as it is now.
+    pub fn take(source: &dyn WindowSource, idle_seconds: Option<u64>) -> Self {
+        Self {
+            windows: source.visible_windows(),
+            active: source.active_window().map(|window| window.id),
+            idle_seconds,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct RunningApp {
+    start_time: u64,
+    last_seen: u64,
+    focused: bool,
+}
+
+pub struct Tracker {
+    config: TrackerConfig,
+    rules: ActiveRules,
+    running: HashMap<(String, String), RunningApp>,
+    /// Start of the idle stretch not yet written out, while the user is away.
+    idle_since: Option<u64>,
+    /// Time of the previous tick.
+    last_tick: Option<u64>,
+}
+
+impl Tracker {
+    pub fn new(config: TrackerConfig, rules: ActiveRules) -> Self {
+        Self {
+            config,
+            rules,
+            running: HashMap::new(),
+            idle_since: None,
+            last_tick: None,
+        }
+    }
+
+    /// Feed one poll taken at `now` and return the sessions that are ready
+    /// to be stored.
+    ///
+    /// A window missing from the snapshot is closed at the last tick it was
+    /// seen. A window that has been open for `flush_interval` seconds is
+    /// written out up to `now` and keeps being tracked from `now` on. A window
+    /// that gained or lost focus is written out up to `now` as well.
+    ///
+    /// While the snapshot reports at least `idle_threshold` idle seconds no
+    /// window accrues time; the stretch is written out as `Idle` sessions,
+    /// flushed on the same interval, and ends at the last input once the user
+    /// is back.
+    ///
+    /// A tick more than `SUSPEND_POLLS` poll intervals after the previous
+    /// one, or before it, ends every window and idle stretch at the previous
+    /// tick and starts afresh from `now`.
+    pub fn tick(&mut self, now: u64, snapshot: Snapshot) -> Vec<AppSession> {
+        let mut sessions = Vec::new();
+        // Rule edits take effect from the next tick on
+        let categorizer = self.rules.current();
+        let idle_seconds = snapshot.idle_seconds.unwrap_or(0);
+
+        let mut previous = self.last_tick.replace(now);
+        if let Some(last_tick) = previous.filter(|last| self.is_discontinuity(*last, now)) {
+            // Suspended, or the clock jumped: nothing was seen in between
+            self.close_running(&categorizer, &mut sessions);
+            if let Some(start) = self.idle_since.take() {
+                if last_tick > start {
+                    sessions.push(idle_session(start, last_tick));
+                }
+            }
+            previous = None;
+        }
+
+        if idle_seconds >= self.config.idle_threshold {
+            match self.idle_since {
+                None => {
+                    // Just went idle: close every window where it was last
+                    // seen and count the time since as idle
+                    self.close_running(&categorizer, &mut sessions);
+                    self.idle_since = Some(previous.unwrap_or(now));
+                }
+                Some(start) if now.saturating_sub(start) >= self.config.flush_interval => {
+                    sessions.push(idle_session(start, now));
+                    self.idle_since = Some(now);
+                }
+                Some(_) => {}
+            }
+            return sessions;
+        }
+
+        if let Some(start) = self.idle_since.take() {
+            let back_at = now.saturating_sub(idle_seconds).max(start);
+            if back_at > start {
+                sessions.push(idle_session(start, back_at));
+            }
+        }
+
+        // Windows sharing a title and process make up one session, focused
+        // if any of them is
+        let mut visible: HashMap<(String, String), bool> = HashMap::new();
+        for window in snapshot.windows {
+            let focused = snapshot.active == Some(window.id);
+            *visible.entry(window.into_key()).or_default() |= focused;
+        }
+
+        for (key, focused) in visible {
+            if let Some(app) = self.running.get_mut(&key) {
+                if app.focused != focused {
+                    sessions.push(make_session(&categorizer, &key.0, &key.1, app, now));
+                    app.start_time = now;
+                    app.focused = focused;
+                }
+                app.last_seen = now;
+            } else {
+                self.running.insert(
+                    key,
+                    RunningApp {
+                        start_time: now,
+                        last_seen: now,
+                        focused,
+                    },
+                );
+            }
+        }
+
+        let flush_interval = self.config.flush_interval;
+
+        self.running.retain(|(title, process), app| {
+            let inactive = app.last_seen < now;
+            let should_flush = now.saturating_sub(app.start_time) >= flush_interval;
+
+            if !inactive && !should_flush {
+                return true; // still tracking, nothing to do yet
+            }
+
+            let end_time = if inactive { app.last_seen } else { now };
+            sessions.push(make_session(&categorizer, title, process, app, end_time));
+
+            if inactive {
+                return false; // stop tracking, the window disappeared
+            }
+
+            // Start the next flush window from now
+            app.start_time = now;
+            app.last_seen = now;
+            true
+        });
+
+        sessions
+    }
+
+    /// Stop tracking every window, closing each at the last tick it was seen.
+    fn close_running(&mut self, categorizer: &Categorizer, sessions: &mut Vec<AppSession>) {
+        for ((title, process), app) in self.running.drain() {
+            sessions.push(make_session(categorizer, &title, &process, &app, app.last_seen));
+        }
+    }
+
+    /// Whether the tick at `now` cannot have followed the one at `last_tick`
+    /// directly.
+    fn is_discontinuity(&self, last_tick: u64, now: u64) -> bool {
+        let longest = self.config.poll_interval.saturating_mul(SUSPEND_POLLS);
+        now < last_tick || Duration::from_secs(now - last_tick) > longest
+    }
+
+    /// Poll `source` and `idle` forever, storing the sessions each `tick`
+    /// produces in one transaction. Failed writes are retried, see
+    /// `MAX_RETRY_DELAY`. Changed `settings` take effect from the next tick.
+    pub async fn run(
+        mut self,
+        db: Db,
+        settings: ActiveSettings,
+        source: Arc<dyn WindowSource>,
+        idle: Arc<dyn IdleDetector>,
+        clock: Arc<dyn Clock>,
+    ) {
+        let mut pending: Vec<AppSession> = Vec::new();
+        let mut failures = 0;
+        let mut retry_at = 0;
+
+        loop {
+            self.config = settings.current().tracker_config();
+            let now = clock.now();
+            let snapshot = Snapshot::take(source.as_ref(), idle.idle_seconds());
+
+            pending.extend(self.tick(now, snapshot));
+
+            if now >= retry_at {
+                match save_sessions(&mut db.lock(), &pending) {
+                    Ok(()) => {
+                        if failures > 0 {
+                            eprintln!("Tracker: stored {} held-back sessions", pending.len());
+                        }
+                        pending.clear();
+                        failures = 0;
+                    }
+                    Err(e) => {
+                        failures += 1;
+                        let delay = retry_delay(failures);
+                        retry_at = now + delay;
+                        eprintln!(
+                            "Tracker: could not store {} sessions (attempt {}), retrying in {}s: {}",
+                            pending.len(),
+                            failures,
+                            delay,
+                            e
+                        );
+
+                        if pending.len() > MAX_PENDING_SESSIONS {
+                            let dropped = pending.len() - MAX_PENDING_SESSIONS;
+                            pending.drain(..dropped);
+                            eprintln!("Tracker: dropped the {} oldest held-back sessions", dropped);
+                        }
+                    }
+                }
+            }
+
+            tokio::time::sleep(self.config.poll_interval).await;
+        }
+    }
+}
+
+/// Seconds to wait after the `failures`th failed write in a row: 2, 4, 8,
+/// ... up to `MAX_RETRY_DELAY`.
+fn retry_delay(failures: u32) -> u64 {
+    1u64.checked_shl(failures).unwrap_or(u64::MAX).min(MAX_RETRY_DELAY)
+}
+
+fn make_session(
+    categorizer: &Categorizer,
+    title: &str,
+    process: &str,
+    app: &RunningApp,
+    end_time: u64,
+) -> AppSession {
+    let categorization = categorizer.categorize(title, process);
+
+    AppSession {
+        app_name: process.to_string(),
+        window_title: title.to_string(),
+        category: categorization.category,
+        start_time: app.start_time,
+        // A clock stepping backwards must not produce a negative duration
+        end_time: end_time.max(app.start_time),
+        is_focused: app.focused,
+        rule_id: categorization.rule_id,
+    }
+}
+
+fn idle_session(start_time: u64, end_time: u64) -> AppSession {
+    AppSession {
+        app_name: IDLE_CATEGORY.to_string(),
+        window_title: String::new(),
+        category: IDLE_CATEGORY.to_string(),
+        start_time,
+        end_time,
+        is_focused: false,
+        rule_id: None,
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rules::default_rules;
+    use crate::source::mock::Frame;
+    use crate::source::MockWindowSource;
+
+    const T0: u64 = 1_700_000_000;
+
+    fn window(id: u64, title: &str, process: &str) -> WindowInfo {
+        WindowInfo {
+            id,
+            title: title.to_string(),
+            process: process.to_string(),
+        }
+    }
+
+    fn editor() -> WindowInfo {
+        window(1, "main.rs - deskflow", "code")
+    }
+
+    fn browser() -> WindowInfo {
+        window(2, "Pull requests - GitHub", "chrome")
+    }
+
+    fn frame(visible: &[WindowInfo], active: Option<&WindowInfo>) -> Frame {
+        Frame {
+            active: active.cloned(),
+            visible: visible.to_vec(),
+            processes: Vec::new(),
+        }
+    }
+
+    fn tracker(flush_interval: u64) -> Tracker {
+        let config = TrackerConfig {
+            flush_interval,
+            idle_threshold: 60,
+            ..TrackerConfig::default()
+        };
+        let rules = ActiveRules::new(Categorizer::new(&default_rules()).unwrap());
+        Tracker::new(config, rules)
+    }
+
+    /// Tick once a second over `frames`, each paired with the idle seconds
+    /// reported alongside it, and collect everything written out.
+    fn replay(tracker: &mut Tracker, frames: Vec<(Frame, u64)>) -> Vec<AppSession> {
+        let timed = frames.into_iter().enumerate().map(|(i, (frame, idle))| (i as u64, frame, idle));
+        replay_at(tracker, timed.collect()).into_iter().flatten().collect()
+    }
+
+    /// Tick at `T0` plus each given offset and return what every tick wrote
+    /// out.
+    fn replay_at(tracker: &mut Tracker, ticks: Vec<(u64, Frame, u64)>) -> Vec<Vec<AppSession>> {
+        let times: Vec<_> = ticks.iter().map(|(offset, _, idle)| (T0 + offset, *idle)).collect();
+        let source = MockWindowSource::new(ticks.into_iter().map(|(_, frame, _)| frame));
+        times
+            .into_iter()
+            .map(|(now, idle_seconds)| {
+                let sessions = tracker.tick(now, Snapshot::take(&source, Some(idle_seconds)));
+                source.advance();
+                sessions
+            })
+            .collect()
+    }
+
+    fn spans(sessions: &[AppSession]) -> Vec<(&str, u64, u64, bool)> {
+        sessions
+            .iter()
+            .map(|s| (s.app_name.as_str(), s.start_time - T0, s.end_time - T0, s.is_focused))
+            .collect()
+    }
+
+    #[test]
+    fn categorizes_scripted_windows() {
+        let both = [editor(), browser()];
+        let frames = vec![(frame(&both, Some(&editor())), 0); 6];
+
+        let mut sessions = replay(&mut tracker(5), frames);
+        sessions.sort_by(|a, b| a.app_name.cmp(&b.app_name));
+
+        assert_eq!(spans(&sessions), vec![("chrome", 0, 5, false), ("code", 0, 5, true)]);
+        assert_eq!(sessions[0].category, "Work");
+        assert_eq!(sessions[0].rule_id.as_deref(), Some("browser-code-hosting"));
+        assert_eq!(sessions[1].rule_id.as_deref(), Some("vscode"));
+    }
+
+    #[test]
+    fn going_idle_closes_windows_where_they_were_last_seen() {
+        let editing = frame(&[editor()], Some(&editor()));
+        let ticks = vec![
+            (0, editing.clone(), 0),
+            (1, editing.clone(), 1),
+            (2, editing.clone(), 60),
+            (3, editing.clone(), 61),
+        ];
+
+        let written = replay_at(&mut tracker(60), ticks);
+
+        assert_eq!(spans(&written[2]), vec![("code", 0, 1, true)]);
+        assert!(written[3].is_empty());
+    }
+
+    #[test]
+    fn suspend_closes_everything_at_the_last_tick() {
+        let editing = frame(&[editor()], Some(&editor()));
+        let ticks = vec![
+            (0, editing.clone(), 0),
+            (1, editing.clone(), 0),
+            (3600, editing.clone(), 0),
+            (3601, frame(&[], None), 0),
+        ];
+
+        let written = replay_at(&mut tracker(60), ticks);
+
+        assert_eq!(spans(&written[2]), vec![("code", 0, 1, true)]);
+        assert_eq!(spans(&written[3]), vec![("code", 3600, 3600, true)]);
+    }
+
+    #[test]
+    fn suspend_while_idle_ends_the_idle_stretch_at_the_last_tick() {
+        let editing = frame(&[editor()], Some(&editor()));
+        let ticks = vec![
+            (0, editing.clone(), 60),
+            (1, editing.clone(), 61),
+            (3600, editing.clone(), 0),
+        ];
+
+        let written = replay_at(&mut tracker(60), ticks);
+
+        assert_eq!(spans(&written[2]), vec![("Idle", 0, 1, false)]);
+    }
+
+    #[test]
+    fn clock_stepping_backwards_never_ends_before_start() {
+        let editing = frame(&[editor(), browser()], Some(&editor()));
+        let browsing = frame(&[editor(), browser()], Some(&browser()));
+        let ticks = vec![(10, editing.clone(), 0), (11, editing, 0), (5, browsing.clone(), 0), (6, browsing, 0)];
+
+        let written: Vec<_> = replay_at(&mut tracker(60), ticks).into_iter().flatten().collect();
+
+        assert!(written.iter().all(|s| s.end_time >= s.start_time));
+        let mut closed = spans(&written);
+        closed.sort();
+        assert_eq!(closed, vec![("chrome", 10, 11, false), ("code", 10, 11, true)]);
+    }
+
+    #[test]
+    fn sessions_never_end_before_they_start() {
+        let categorizer = Categorizer::new(&default_rules()).unwrap();
+        let app = RunningApp {
+            start_time: T0,
+            last_seen: T0,
+            focused: true,
+        };
+
+        let session = make_session(&categorizer, "main.rs", "code", &app, T0 - 30);
+
+        assert_eq!((session.start_time, session.end_time), (T0, T0));
+    }
+
+    #[test]
+    fn flushes_exactly_at_flush_interval() {
+        let editing = frame(&[editor()], Some(&editor()));
+
+        let written = replay_at(&mut tracker(5), (0..=10).map(|t| (t, editing.clone(), 0)).collect());
+
+        let flushed_at: Vec<_> = (0..written.len()).filter(|i| !written[*i].is_empty()).collect();
+        assert_eq!(flushed_at, vec![5, 10]);
+        assert_eq!(spans(&written[5]), vec![("code", 0, 5, true)]);
+        assert_eq!(spans(&written[10]), vec![("code", 5, 10, true)]);
+    }
+
+    #[test]
+    fn window_that_disappears_is_closed_where_last_seen() {
+        let both = frame(&[editor(), browser()], Some(&editor()));
+        let editor_only = frame(&[editor()], Some(&editor()));
+
+        let written = replay_at(
+            &mut tracker(60),
+            vec![(0, both.clone(), 0), (1, both.clone(), 0), (2, both, 0), (3, editor_only, 0)],
+        );
+
+        assert!(written[..3].iter().all(Vec::is_empty));
+        assert_eq!(spans(&written[3]), vec![("chrome", 0, 2, false)]);
+    }
+
+    #[test]
+    fn focus_change_splits_the_session() {
+        let both = [editor(), browser()];
+        let editing = frame(&both, Some(&editor()));
+        let browsing = frame(&both, Some(&browser()));
+        let mut ticks = vec![(0, editing.clone(), 0), (1, editing, 0)];
+        ticks.extend((2..=7).map(|t| (t, browsing.clone(), 0)));
+
+        let written = replay_at(&mut tracker(5), ticks);
+
+        let mut split = spans(&written[2]);
+        split.sort();
+        assert_eq!(split, vec![("chrome", 0, 2, false), ("code", 0, 2, true)]);
+
+        // Both carry on from the split with their new focus
+        let mut flushed = spans(&written[7]);
+        flushed.sort();
+        assert_eq!(flushed, vec![("chrome", 2, 7, true), ("code", 2, 7, false)]);
+    }
+
+    #[test]
+    fn idle_and_back() {
+        let editing = frame(&[editor()], Some(&editor()));
+        let mut tracker = tracker(5);
+        // Sparse ticks, so the jumps below don't look like a suspend
+        tracker.config.poll_interval = Duration::from_secs(10);
+        let ticks = vec![
+            (0, editing.clone(), 0),
+            (1, editing.clone(), 0),
+            // Last input was at 1
+            (61, editing.clone(), 60),
+            (66, editing.clone(), 65),
+            (67, editing.clone(), 66),
+            // Input at 69, noticed at 70
+            (70, editing.clone(), 1),
+            (75, editing.clone(), 0),
+        ];
+
+        let written = replay_at(&mut tracker, ticks);
+
+        assert_eq!(spans(&written[2]), vec![("code", 0, 1, true)]);
+        assert_eq!(spans(&written[3]), vec![("Idle", 1, 66, false)]);
+        assert!(written[4].is_empty());
+        assert_eq!(spans(&written[5]), vec![("Idle", 66, 69, false)]);
+        assert_eq!(spans(&written[6]), vec![("code", 70, 75, true)]);
+    }
+
+    #[test]
+    fn focus_follows_the_handle_not_the_name() {
+        // Two windows of one app with the same title, the second focused
+        let other_editor = window(3, "main.rs - deskflow", "code");
+        let both = [editor(), other_editor.clone(), browser()];
+        let ticks = vec![
+            (0, frame(&both, Some(&other_editor)), 0),
+            (1, frame(&both, Some(&editor())), 0),
+            (2, frame(&both, Some(&browser())), 0),
+        ];
+
+        let written = replay_at(&mut tracker(60), ticks);
+
+        // Focus moving between the two is no change for the session
+        assert!(written[1].is_empty());
+        let mut split = spans(&written[2]);
+        split.sort();
+        assert_eq!(split, vec![("chrome", 0, 2, false), ("code", 0, 2, true)]);
+    }
+}
@@ -1,9 +1,10 @@
 import { invoke } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
 import "./App.css";
-import { useEffect, useState } from 'react';
+import { useEffect, useMemo, useState } from 'react';
 import viewIcon from './assets/view.png';
 import hideIcon from './assets/hide.png';
-import { parseISO, format } from 'date-fns';
+import { parseISO, format, subDays } from 'date-fns';
 
 import {
   PieChart, Pie, Cell, Tooltip, ResponsiveContainer,
@@ -26,15 +27,21 @@ const CATEGORY_COLORS: Record<string, string> = {
   'Chatting': '#0066CC',
 };
 
-const productiveCategories = [
-  "Development",
-  "Education",
-  "Work",
-  "Writing",
-  "Research",
-  "Tools",
-  "Design"
-];
+// Not shown in the chart or the list ("Idle" is time away from the keyboard)
+const HIDDEN_CATEGORIES = ["Other", "Idle"];
+
+type CategoryTime = { focused: number; background: number };
+
+type DayScore = { date: string; score: number | null; missing: boolean; avg_7: number | null; avg_30: number | null };
+type ScoreHistory = { days: DayScore[]; threshold: number; current_streak: number; longest_streak: number };
+
+// What a failed command rejects with
+type DeskflowError = { kind: "io" | "sqlite" | "config" | "platform"; message: string };
+
+function describeError(err: unknown) {
+  const e = err as Partial<DeskflowError>;
+  return e && e.kind && e.message ? `${e.kind}: ${e.message}` : String(err);
+}
 
 function formatDuration(seconds: number) {
   const hrs = Math.floor(seconds / 3600);
@@ -46,16 +53,23 @@ function App() {
   const [activeApp, setActiveApp] = useState({ title: "", process: "" });
   const [windows, setWindows] = useState<[string, string][]>([]);
 
-  const [categorySummary, setCategorySummary] = useState<Record<string, number>>({});
+  const [categoryTimes, setCategoryTimes] = useState<Record<string, CategoryTime>>({});
+  // Charts only count focused time; background time is listed alongside
+  const categorySummary = useMemo(
+    () => Object.fromEntries(
+      Object.entries(categoryTimes).map(([category, time]) => [category, time.focused])
+    ) as Record<string, number>,
+    [categoryTimes]
+  );
   const [showModal, setShowModal] = useState(false);
 
   const [score, setScore] = useState<{ percent: number; rating: string } | null>(null);
 
-  const [scoreHistory, setScoreHistory] = useState<{ date: string; score: number }[]>([]);
+  const [scoreHistory, setScoreHistory] = useState<ScoreHistory | null>(null);
 
 
   const pieData = Object.entries(categorySummary)
-    .filter(([category]) => category !== "Other")
+    .filter(([category]) => !HIDDEN_CATEGORIES.includes(category))
     .sort(([a], [b]) => a.localeCompare(b)) // Sort by category name for consistent order
     .map(([category, seconds]) => ({
       name: category,
@@ -65,6 +79,10 @@ function App() {
 
   useEffect(() => {
     invoke('init_position')
+    // The HUD size and margins are settings; follow them when they change
+    const unlistenSettings = listen("settings-changed", () => {
+      invoke('init_position').catch(err => console.error("Failed to reposition", describeError(err)));
+    });
 
     // Start checking the active window every 5 seconds
     const interval = setInterval(async () => {
@@ -80,56 +98,53 @@ function App() {
         invoke<[string, string][]>('get_all_visible_windows').then(setWindows);
 
       } catch (error) {
-        console.error("Failed to get active app:", error);
+        console.error("Failed to get active app:", describeError(error));
       }
     }, 5000);
 
     // Every 10 sec: fetch category summary
     const summaryInterval = setInterval(async () => {
       try {
-        const result = await invoke<Record<string, number>>("get_category_summary");
-        setCategorySummary(result);
+        const result = await invoke<Record<string, CategoryTime>>("get_category_summary");
+        setCategoryTimes(result);
       } catch (err) {
-        console.error("Failed to fetch summary", err);
+        console.error("Failed to fetch summary", describeError(err));
       }
     }, 10000);
 
 
-    invoke<{ date: string; score: number }[]>("get_last_five_scores")
+    const today = new Date();
+    invoke<ScoreHistory>("get_scores", {
+      from: format(subDays(today, 6), 'yyyy-MM-dd'),
+      to: format(today, 'yyyy-MM-dd'),
+    })
       .then(setScoreHistory)
-      .catch(err => console.error("Error fetching score history", err));
+      .catch(err => console.error("Error fetching score history", describeError(err)));
 
     return () => {
       clearInterval(interval);
       clearInterval(summaryInterval);
+      unlistenSettings.then(unlisten => unlisten());
 
     };
   }, [])
 
-  // Every 30 sec: fetch productivity score
+  // The score is computed (and stored at the end of the day) by the backend
   useEffect(() => {
-    const totalTime = Object.values(categorySummary).reduce((a, b) => a + b, 0);
+    invoke<number | null>("get_today_score")
+      .then(result => {
+        const percent = result ?? 0;
 
-    const productiveTime = Object.entries(categorySummary)
-      .filter(([category]) => productiveCategories.includes(category))
-      .reduce((sum, [_, time]) => sum + time, 0);
+        const rating =
+          percent >= 90 ? "🌟 Excellent" :
+            percent >= 70 ? "✅ Good" :
+              percent >= 50 ? "⚠️ Average" :
+                "❌ Needs Improvement";
 
-    const percent = totalTime > 0 ? parseFloat(((productiveTime / totalTime) * 100).toFixed(1)) : 0;
-
-    const rating =
-      percent >= 90 ? "🌟 Excellent" :
-        percent >= 70 ? "✅ Good" :
-          percent >= 50 ? "⚠️ Average" :
-            "❌ Needs Improvement";
-
-    setScore({ percent, rating });
-
-
-    // Store the score to database
-    const today = new Date().toISOString().split('T')[0]; // YYYY-MM-DD format
-    invoke('store_score', { date: today, score: percent })
-      .catch(err => console.error('Failed to store score:', err));
-  }, [categorySummary]);  // ✅ Run this logic every time categorySummary updates
+        setScore({ percent, rating });
+      })
+      .catch(err => console.error("Failed to fetch score", describeError(err)));
+  }, [categoryTimes]);  // ✅ Refresh together with the category summary
 
 
   // Custom label function for inside the pie slices
@@ -195,10 +210,13 @@ function App() {
             <h3>📊 Time Spent</h3>
             <ul>
               {Object.entries(categorySummary)
-                .filter(([category]) => category !== "Other") // 🔥 Hide "Other"
+                .filter(([category]) => !HIDDEN_CATEGORIES.includes(category)) // 🔥 Hide "Other"
                 .map(([category, seconds]) => (
                   <li key={category}>
                     <strong>{category}</strong>: {formatDuration(seconds)}
+                    {categoryTimes[category]?.background >= 60 && (
+                      <span className="background-time"> (+{formatDuration(categoryTimes[category].background)} visible)</span>
+                    )}
                   </li>
                 ))}
             </ul>
@@ -265,11 +283,14 @@ function App() {
         )}
 
         {/* 📈 Score History */}
-        {scoreHistory.length > 0 && (
+        {scoreHistory && scoreHistory.days.some(day => !day.missing) && (
           <div className="score-history-box">
-            <h4>📅 Last 5 Days Productivity</h4>
+            <h4>📅 Last 7 Days Productivity</h4>
+            <div className="streak-info">
+              🔥 Streak: <strong>{scoreHistory.current_streak}</strong> days (best {scoreHistory.longest_streak})
+            </div>
             <ResponsiveContainer width="100%" height={200}>
-              <BarChart data={scoreHistory} margin={{ top: 10, right: 20, left: 0, bottom: 10 }} style={{ backgroundColor: 'transparent' }}>
+              <BarChart data={scoreHistory.days} margin={{ top: 10, right: 20, left: 0, bottom: 10 }} style={{ backgroundColor: 'transparent' }}>
                 <Tooltip
                   contentStyle={{ backgroundColor: 'rgba(0, 0, 0, 1)', border: '1px solid #ccc' }}
                   formatter={(value, name) => [`${value}%`, name]}